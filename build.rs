@@ -1,22 +1,71 @@
+use std::{env, fs, path::Path};
+
 use convert_case::{Case, Casing};
 use ethers::contract::Abigen;
 
-fn bindgen(contract_name: &str) -> Result<(), Box<dyn std::error::Error>> {
+/// Output of `npx hardhat compile`, preferred over `abi/` when present.
+fn hardhat_artifact_path(contract_name: &str) -> String {
+    format!("./artifacts/contracts/{0}.sol/{0}.json", contract_name)
+}
+
+/// Reads the ABI and creation bytecode of a contract. `abi/<name>.json` may
+/// hold either the bare ABI or a whole Hardhat artifact.
+fn load_artifact(
+    contract_name: &str,
+) -> Result<(serde_json::Value, Option<String>), Box<dyn std::error::Error>> {
+    let artifact_path = hardhat_artifact_path(contract_name);
     let abi_path = format!("./abi/{}.json", contract_name);
-    let bindings = Abigen::new(contract_name, abi_path)?.generate()?;
+    // Both are watched even when missing, so that the first `npx hardhat
+    // compile` is picked up. Cargo reruns the script on every build until the
+    // artifact exists.
+    for path in [&artifact_path, &abi_path] {
+        println!("cargo:rerun-if-changed={}", path);
+    }
+    let path = if Path::new(&artifact_path).exists() {
+        artifact_path
+    } else {
+        abi_path
+    };
+    let json: serde_json::Value = serde_json::from_str(&fs::read_to_string(&path)?)?;
+    if json.is_array() {
+        return Ok((json, None));
+    }
+    let abi = json
+        .get("abi")
+        .cloned()
+        .ok_or_else(|| format!("{} is neither an ABI nor a Hardhat artifact", path))?;
+    let bytecode = json
+        .get("bytecode")
+        .and_then(|bytecode| bytecode.as_str())
+        .filter(|bytecode| !bytecode.trim_start_matches("0x").is_empty())
+        .map(str::to_string);
+    Ok((abi, bytecode))
+}
+
+fn bindgen(contract_name: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let (abi, bytecode) = load_artifact(contract_name)?;
+    let out_dir = env::var("OUT_DIR")?;
+
+    // Bindings are generated from the bare ABI so that they look the same
+    // with or without bytecode, `deploy` lives in `bindings/mod.rs`. It is
+    // passed inline, a file in `OUT_DIR` would be watched and rewritten on
+    // every run.
+    let bindings = Abigen::new(contract_name, serde_json::to_string(&abi)?)?.generate()?;
 
     bindings.write_to_file(format!(
         "./src/bindings/{}.rs",
         contract_name.from_case(Case::Camel).to_case(Case::Snake)
     ))?;
+    fs::write(
+        format!("{}/{}.bytecode", out_dir, contract_name),
+        bytecode.unwrap_or_default(),
+    )?;
 
     Ok(())
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // println!("cargo:rerun-if-changed=build.rs");
-    // println!("cargo:rerun-if-changed=./abi/OperatorManager.json");
-    // println!("cargo:rerun-if-changed=./src/bindings/*.rs");
+    println!("cargo:rerun-if-changed=build.rs");
 
     shadow_rs::new()?;
     bindgen("Lock")?;
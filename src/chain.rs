@@ -0,0 +1,243 @@
+use std::fmt;
+
+use ethers::{
+    providers::{Middleware, Provider, ProviderError, RpcError},
+    types::{BlockId, BlockNumber, U256},
+};
+
+use crate::{
+    config::redact_url,
+    transport::{Transport, TransportError},
+    COUNTER_CLIENT,
+};
+
+/// Chains where transactions spend real funds.
+const PRODUCTION_CHAINS: &[(u64, &str)] = &[
+    (1, "Ethereum"),
+    (10, "OP Mainnet"),
+    (56, "BNB Smart Chain"),
+    (100, "Gnosis"),
+    (137, "Polygon"),
+    (291, "Orderly"),
+    (324, "zkSync Era"),
+    (1101, "Polygon zkEVM"),
+    (5000, "Mantle"),
+    (8453, "Base"),
+    (42161, "Arbitrum One"),
+    (42170, "Arbitrum Nova"),
+    (43114, "Avalanche C-Chain"),
+    (59144, "Linea"),
+    (81457, "Blast"),
+    (534352, "Scroll"),
+];
+
+/// Name of the production chain `chain_id` belongs to.
+pub fn production_chain(chain_id: u64) -> Option<&'static str> {
+    PRODUCTION_CHAINS
+        .iter()
+        .find(|(id, _)| *id == chain_id)
+        .map(|(_, name)| *name)
+}
+
+/// Failures talking to the node, reported instead of panicking.
+#[derive(Debug, thiserror::Error)]
+pub enum ChainError {
+    #[error("failed to connect to {url}: {source}")]
+    Connect {
+        url: String,
+        #[source]
+        source: TransportError,
+    },
+    #[error("{method} failed: {source}")]
+    Rpc {
+        method: &'static str,
+        #[source]
+        source: ProviderError,
+    },
+    #[error("the node returned no latest block")]
+    MissingBlock,
+    #[error("eth_feeHistory returned no base fees")]
+    EmptyFeeHistory,
+    #[error("{url} is on chain {chain_id} but chain {expected} is configured")]
+    WrongChain {
+        url: String,
+        chain_id: u64,
+        expected: u64,
+    },
+    #[error("{url} is on chain {chain_id} but {other_url} on chain {other_chain_id}")]
+    MixedChains {
+        url: String,
+        chain_id: u64,
+        other_url: String,
+        other_chain_id: u64,
+    },
+    #[error("chain {chain_id} is {name} mainnet, refusing to sign for it without --allow-mainnet")]
+    Production { chain_id: u64, name: &'static str },
+}
+
+impl ChainError {
+    pub fn rpc(method: &'static str) -> impl FnOnce(ProviderError) -> Self {
+        move |source| ChainError::Rpc { method, source }
+    }
+}
+
+/// Which chains the operator key may sign for, checked before anything is
+/// sent.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ChainGuard {
+    /// Refuse every other chain, e.g. the one of the selected network.
+    pub expected_chain_id: Option<u64>,
+    /// Sign for the chains of [`production_chain`].
+    pub allow_mainnet: bool,
+}
+
+impl ChainGuard {
+    /// Asks every endpoint behind `provider` for its chain id, the pool
+    /// would answer for one of them only. They must agree with each other
+    /// and with the expected chain id. Endpoints that do not answer are left
+    /// to the pool's health checks.
+    pub async fn verify(&self, provider: &Provider<Transport>) -> Result<u64, ChainError> {
+        let mut first: Option<(String, u64)> = None;
+        let mut last_err = None;
+        for (url, transport) in endpoints(provider.as_ref()) {
+            let chain_id = match Provider::new(transport).get_chainid().await {
+                Ok(chain_id) => chain_id.as_u64(),
+                Err(err) => {
+                    tracing::warn!(
+                        target: COUNTER_CLIENT,
+                        "eth_chainId of {} failed with err: {}",
+                        redact_url(&url),
+                        err
+                    );
+                    last_err = Some(err);
+                    continue;
+                }
+            };
+            if let Some(expected) = self.expected_chain_id.filter(|id| *id != chain_id) {
+                return Err(ChainError::WrongChain {
+                    url: redact_url(&url),
+                    chain_id,
+                    expected,
+                });
+            }
+            match &first {
+                Some((other_url, other_chain_id)) if *other_chain_id != chain_id => {
+                    return Err(ChainError::MixedChains {
+                        url: redact_url(&url),
+                        chain_id,
+                        other_url: redact_url(other_url),
+                        other_chain_id: *other_chain_id,
+                    });
+                }
+                Some(_) => {}
+                None => first = Some((url, chain_id)),
+            }
+        }
+        let Some((_, chain_id)) = first else {
+            return Err(match last_err {
+                Some(source) => ChainError::Rpc {
+                    method: "eth_chainId",
+                    source,
+                },
+                None => ChainError::Connect {
+                    url: "none".to_string(),
+                    source: TransportError::NoEndpoint,
+                },
+            });
+        };
+        if let Some(name) = production_chain(chain_id) {
+            if !self.allow_mainnet {
+                return Err(ChainError::Production { chain_id, name });
+            }
+            tracing::warn!(target: COUNTER_CLIENT, "signing for {} mainnet, chain {}", name, chain_id);
+        }
+        Ok(chain_id)
+    }
+}
+
+/// The endpoints behind `transport` with their urls.
+fn endpoints(transport: &Transport) -> Vec<(String, Transport)> {
+    match transport {
+        Transport::Pool(pool) => pool
+            .endpoints()
+            .iter()
+            .map(|endpoint| (endpoint.url.clone(), endpoint.transport().clone()))
+            .collect(),
+        Transport::Split { read, write } => {
+            let mut all = endpoints(read);
+            all.extend(endpoints(write));
+            all
+        }
+        other => vec![(other.name().to_string(), other.clone())],
+    }
+}
+
+/// What the connected chain supports, probed once at startup.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChainCapabilities {
+    pub chain_id: u64,
+    /// Blocks carry a base fee, so type-2 transactions are accepted.
+    pub london: bool,
+    /// The node answers `eth_maxPriorityFeePerGas`.
+    pub max_priority_fee: bool,
+}
+
+impl ChainCapabilities {
+    pub async fn probe(provider: &Provider<Transport>) -> Result<Self, ChainError> {
+        let chain_id = provider
+            .get_chainid()
+            .await
+            .map_err(ChainError::rpc("eth_chainId"))?
+            .as_u64();
+        let london = latest_base_fee(provider).await?.is_some();
+        let max_priority_fee = match max_priority_fee(provider).await {
+            Ok(_) => true,
+            // An error response means the method is not there, anything
+            // else is a transport problem worth surfacing.
+            Err(ChainError::Rpc { source, .. }) if source.as_error_response().is_some() => false,
+            Err(err) => return Err(err),
+        };
+        Ok(Self {
+            chain_id,
+            london,
+            max_priority_fee,
+        })
+    }
+}
+
+impl fmt::Display for ChainCapabilities {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let yes_no = |b: bool| if b { "yes" } else { "no" };
+        write!(
+            f,
+            "chain id {} london: {} eth_maxPriorityFeePerGas: {}",
+            self.chain_id,
+            yes_no(self.london),
+            yes_no(self.max_priority_fee)
+        )
+    }
+}
+
+/// Base fee of the latest block, `None` before London.
+pub async fn latest_base_fee(provider: &Provider<Transport>) -> Result<Option<U256>, ChainError> {
+    let block = provider
+        .get_block(BlockId::Number(BlockNumber::Latest))
+        .await
+        .map_err(ChainError::rpc("eth_getBlockByNumber"))?
+        .ok_or(ChainError::MissingBlock)?;
+    Ok(block.base_fee_per_gas)
+}
+
+pub async fn gas_price(provider: &Provider<Transport>) -> Result<U256, ChainError> {
+    provider
+        .get_gas_price()
+        .await
+        .map_err(ChainError::rpc("eth_gasPrice"))
+}
+
+pub async fn max_priority_fee(provider: &Provider<Transport>) -> Result<U256, ChainError> {
+    provider
+        .request("eth_maxPriorityFeePerGas", ())
+        .await
+        .map_err(ChainError::rpc("eth_maxPriorityFeePerGas"))
+}
@@ -0,0 +1,1052 @@
+use std::{env, path::PathBuf, time::Duration};
+
+use anyhow::{anyhow, bail};
+use clap::{
+    parser::ValueSource, ArgMatches, Args, CommandFactory, FromArgMatches, Parser, Subcommand,
+};
+use ethers::{
+    types::{Address, U256},
+    utils::{parse_ether, parse_units},
+};
+
+use ethers_counter::{
+    chain::ChainGuard,
+    config::{Config, Network, Workload},
+    endpoints::{EndpointConfig, Routing},
+    export::{ExportConfig, OutputFormat},
+    fees::{FeeConfig, FeeStrategy, FeeStrategyKind, GasPolicy},
+    load::{parse_duration, Burst, LoadPreset, LoadProfile, Stage},
+    replace::ReplaceAction,
+    secret::SecretString,
+    signer::{KeySource, KeystorePassword},
+    submit_error::{PolicyOverride, RecoveryPolicy},
+    supervisor::SupervisorConfig,
+    tracker::TrackerConfig,
+    wallet_pool::DEFAULT_DERIVATION_PATH,
+    workload::InFlightWindow,
+    ClientConfig, COUNTER_CLIENT,
+};
+
+use crate::commands::deploy::Deployment;
+
+/// Load and correctness tester for the `Lock` counter contract.
+///
+/// Every flag falls back to the environment variable named in its help, and
+/// `.env` is loaded first. Without a subcommand `load` is run.
+#[derive(Debug, Parser)]
+#[command(version, about, args_conflicts_with_subcommands = true)]
+pub struct Cli {
+    #[command(flatten)]
+    pub chain: ChainArgs,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    #[command(flatten)]
+    pub load: LoadArgs,
+}
+
+// Parsed once per process, boxing the big variant buys nothing.
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Send `inc()` according to a load profile and report the outcome.
+    Load(LoadArgs),
+    /// Print the contract state.
+    Read(ReadArgs),
+    /// Call `withdraw()` on the contract.
+    Withdraw(WithdrawArgs),
+    /// Backfill then follow `Withdrawal` events.
+    Watch(WatchArgs),
+    /// Speed up or cancel a pending tx of the operator or a pool account.
+    Replace(ReplaceArgs),
+    /// Deploy a new `Lock` contract.
+    Deploy(DeployArgs),
+    /// Print the report of an exported run.
+    Report(ReportArgs),
+    /// Print the settings `load` would run with, secrets redacted.
+    Config(LoadArgs),
+}
+
+impl Cli {
+    /// The load settings, for the subcommands that have them.
+    fn load_args_mut(&mut self) -> Option<&mut LoadArgs> {
+        match &mut self.command {
+            None => Some(&mut self.load),
+            Some(Command::Load(args) | Command::Config(args)) => Some(args),
+            Some(_) => None,
+        }
+    }
+}
+
+/// The first account of the default BIP-44 Ethereum path.
+const DEFAULT_OPERATOR_PATH: &str = "m/44'/60'/0'/0/0";
+
+/// Config file read when --config is not given, if it exists.
+pub const DEFAULT_CONFIG: &str = "counter.toml";
+
+/// Connection settings shared by every subcommand that talks to the chain.
+#[derive(Debug, Args)]
+pub struct ChainArgs {
+    /// One or more endpoints, comma separated.
+    #[arg(long, global = true, env = "L2_RPC_URL", value_delimiter = ',')]
+    pub rpc_url: Vec<String>,
+
+    /// Send transactions here instead, e.g. the sequencer, while --rpc-url serves reads.
+    #[arg(long, global = true, env = "SUBMIT_RPC_URL", value_delimiter = ',')]
+    pub submit_rpc_url: Vec<String>,
+
+    /// How requests are spread over several endpoints: `round-robin` or `latency`.
+    #[arg(long, global = true, env = "RPC_ROUTING", default_value = "round-robin")]
+    pub rpc_routing: Routing,
+
+    /// Retries of a request after a transport error, on another endpoint where possible.
+    #[arg(long, global = true, env = "RPC_RETRIES", default_value_t = 3)]
+    pub rpc_retries: u32,
+
+    /// Delay before the first retry, doubled on every further one.
+    #[arg(long, global = true, env = "RPC_BACKOFF", value_parser = parse_duration, default_value = "200ms")]
+    pub rpc_backoff: Duration,
+
+    /// How often every endpoint's head is polled.
+    #[arg(long, global = true, env = "RPC_HEALTH_INTERVAL", value_parser = parse_duration, default_value = "5s")]
+    pub rpc_health_interval: Duration,
+
+    #[arg(long, global = true, env = "PRIVATE_KEY", hide_env_values = true)]
+    pub private_key: Option<SecretString>,
+
+    /// Web3 Secret Storage (JSON keystore) file of the operator key, over --private-key.
+    #[arg(long, global = true, env = "KEYSTORE")]
+    pub keystore: Option<PathBuf>,
+
+    /// File holding the keystore password, asked for on the terminal if unset.
+    #[arg(long, global = true, env = "KEYSTORE_PASSWORD_FILE")]
+    pub keystore_password_file: Option<PathBuf>,
+
+    /// Derive the operator key from this BIP-39 mnemonic, over --private-key.
+    #[arg(long, global = true, env = "OPERATOR_MNEMONIC", hide_env_values = true)]
+    pub operator_mnemonic: Option<SecretString>,
+
+    /// Full BIP-32 path of the operator key in --operator-mnemonic.
+    #[arg(long, global = true, env = "OPERATOR_DERIVATION_PATH", default_value = DEFAULT_OPERATOR_PATH)]
+    pub operator_derivation_path: String,
+
+    /// JSON-RPC signer holding the operator key, over every other key source.
+    #[arg(long, global = true, env = "REMOTE_SIGNER_URL")]
+    pub remote_signer: Option<String>,
+
+    /// Account of the remote signer, its first `eth_accounts` entry if unset.
+    #[arg(long, global = true, env = "REMOTE_SIGNER_ADDRESS")]
+    pub remote_signer_address: Option<Address>,
+
+    /// Also read from the legacy `CONCTRACT_ADDR`, then from the `deploy` record of the chain.
+    #[arg(long, global = true, env = "CONTRACT_ADDR")]
+    pub contract: Option<Address>,
+
+    /// Where `deploy` records contract addresses, one file per chain.
+    #[arg(long, global = true, env = "DEPLOYMENTS_DIR", default_value = "deployments")]
+    pub deployments_dir: PathBuf,
+
+    /// File of named networks and load profiles, `counter.toml` if present.
+    #[arg(long, global = true, env = "CONFIG_FILE")]
+    pub config: Option<PathBuf>,
+
+    /// Network of the config file to connect to.
+    #[arg(long, global = true, env = "NETWORK")]
+    pub network: Option<String>,
+
+    /// Refuse to run unless every endpoint is on this chain, the one of --network by default.
+    #[arg(long, global = true, env = "CHAIN_ID")]
+    pub chain_id: Option<u64>,
+
+    /// Run on known production chains such as Ethereum or Orderly; deliberately not read from the environment.
+    #[arg(long, global = true)]
+    pub allow_mainnet: bool,
+}
+
+impl ChainArgs {
+    pub fn rpc_urls(&self) -> anyhow::Result<&[String]> {
+        if self.rpc_url.is_empty() {
+            return Err(anyhow!("missing RPC url, pass --rpc-url or set L2_RPC_URL"));
+        }
+        Ok(&self.rpc_url)
+    }
+
+    pub fn endpoint_config(&self) -> EndpointConfig {
+        EndpointConfig {
+            routing: self.rpc_routing,
+            retries: self.rpc_retries,
+            backoff: self.rpc_backoff,
+            health_interval: self.rpc_health_interval,
+        }
+    }
+
+    pub fn client_config(&self) -> anyhow::Result<ClientConfig> {
+        Ok(ClientConfig {
+            rpc_urls: self.rpc_urls()?.to_vec(),
+            submit_rpc_urls: self.submit_rpc_url.clone(),
+            endpoint: self.endpoint_config(),
+            key: self.key_source()?,
+            guard: ChainGuard {
+                expected_chain_id: self.chain_id,
+                allow_mainnet: self.allow_mainnet,
+            },
+        })
+    }
+
+    /// The first configured of remote signer, keystore, mnemonic and
+    /// private key.
+    pub fn key_source(&self) -> anyhow::Result<KeySource> {
+        if let Some(url) = &self.remote_signer {
+            return Ok(KeySource::Remote {
+                url: url.clone(),
+                address: self.remote_signer_address,
+            });
+        }
+        if let Some(path) = &self.keystore {
+            let password = match &self.keystore_password_file {
+                Some(file) => KeystorePassword::File(file.clone()),
+                None => KeystorePassword::Prompt,
+            };
+            return Ok(KeySource::Keystore {
+                path: path.clone(),
+                password,
+            });
+        }
+        if let Some(phrase) = &self.operator_mnemonic {
+            return Ok(KeySource::Mnemonic {
+                phrase: phrase.clone(),
+                derivation_path: self.operator_derivation_path.clone(),
+            });
+        }
+        match &self.private_key {
+            Some(private_key) => Ok(KeySource::PrivateKey(private_key.clone())),
+            None => Err(anyhow!(
+                "missing operator key, pass --private-key, --keystore, --operator-mnemonic or --remote-signer"
+            )),
+        }
+    }
+
+    /// The config file in use, `None` without --config and `counter.toml`.
+    pub fn config_path(&self) -> Option<PathBuf> {
+        match &self.config {
+            Some(path) => Some(path.clone()),
+            None => Some(PathBuf::from(DEFAULT_CONFIG)).filter(|path| path.exists()),
+        }
+    }
+
+    pub fn load_config(&self) -> anyhow::Result<Option<Config>> {
+        self.config_path()
+            .map(|path| Config::load(&path))
+            .transpose()
+    }
+
+    /// The configured address, else the one `deploy` recorded for `chain_id`.
+    pub fn contract(&self, chain_id: u64) -> anyhow::Result<Address> {
+        if let Some(contract) = self.contract {
+            return Ok(contract);
+        }
+        match Deployment::load(&self.deployments_dir, chain_id)? {
+            Some(deployment) => Ok(deployment.address),
+            None => Err(anyhow!(
+                "missing contract address, pass --contract, set CONTRACT_ADDR or run `deploy`"
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct LoadArgs {
+    /// A profile of the config file, or the preset `smoke` or `soak`.
+    #[arg(long, env = "LOAD_PROFILE", default_value = "smoke")]
+    pub profile: String,
+
+    /// Preset the other load flags start from, the one --profile names by default.
+    #[arg(long, env = "LOAD_PRESET")]
+    pub preset: Option<LoadPreset>,
+
+    /// Stop after this many txs.
+    #[arg(long, env = "LOAD_TX_COUNT")]
+    pub tx_count: Option<u64>,
+
+    /// Constant send rate.
+    #[arg(long, env = "LOAD_TPS")]
+    pub tps: Option<f64>,
+
+    /// Stop after this much time, e.g. `10m`.
+    #[arg(long, env = "LOAD_DURATION", value_parser = parse_duration)]
+    pub duration: Option<Duration>,
+
+    /// Ramp stages `<duration>:<tps>`, comma separated.
+    #[arg(long, env = "LOAD_STAGES", value_delimiter = ',')]
+    pub stages: Vec<Stage>,
+
+    /// Send `<size>` txs back-to-back every `<interval>`, e.g. `50/5s`.
+    #[arg(long, env = "LOAD_BURST")]
+    pub burst: Option<Burst>,
+
+    /// Blocks, counting the inclusion block, before a receipt is final.
+    #[arg(long, env = "CONFIRMATIONS", default_value_t = 1)]
+    pub confirmations: u64,
+
+    /// How long to wait for the remaining txs after the last send.
+    #[arg(long, env = "RECEIPT_TIMEOUT", value_parser = parse_duration, default_value = "60s")]
+    pub receipt_timeout: Duration,
+
+    /// Unused over ws/ipc, where new heads drive the receipt checks.
+    #[arg(long, env = "RECEIPT_POLL_INTERVAL", value_parser = parse_duration, default_value = "1s")]
+    pub receipt_poll_interval: Duration,
+
+    /// Also subscribe to pending transactions to report mempool latency, ws/ipc only.
+    #[arg(long, env = "SUBSCRIBE_PENDING")]
+    pub subscribe_pending: bool,
+
+    /// How often heads are polled to measure replica lag, with --submit-rpc-url.
+    #[arg(long, env = "LAG_POLL_INTERVAL", value_parser = parse_duration, default_value = "100ms")]
+    pub lag_poll_interval: Duration,
+
+    /// Write tx records and the run summary into this directory.
+    #[arg(long, env = "OUTPUT_DIR")]
+    pub output_dir: Option<PathBuf>,
+
+    #[arg(long, env = "OUTPUT_FORMAT", default_value = "all")]
+    pub output_format: OutputFormat,
+
+    #[command(flatten)]
+    pub pool: PoolArgs,
+
+    /// Top every derived account up to this many ether from the operator key, `0` to skip.
+    #[arg(long, env = "POOL_FUND_AMOUNT", value_parser = parse_eth, default_value = "0.01")]
+    pub fund_amount: U256,
+
+    /// Submissions per account allowed in flight at once.
+    #[arg(long, env = "MAX_IN_FLIGHT_PER_ACCOUNT", default_value_t = 1)]
+    pub max_in_flight_per_account: usize,
+
+    /// Submissions across all accounts allowed in flight at once, unbounded if unset.
+    #[arg(long, env = "MAX_IN_FLIGHT")]
+    pub max_in_flight: Option<usize>,
+
+    #[command(flatten)]
+    pub fees: FeeArgs,
+
+    #[command(flatten)]
+    pub submit: SubmitArgs,
+}
+
+/// Sender accounts of `load` and `replace`; without a mnemonic the operator key sends everything.
+#[derive(Debug, Args)]
+pub struct PoolArgs {
+    /// Derive the sender accounts from this BIP-39 mnemonic.
+    #[arg(long, env = "MNEMONIC", hide_env_values = true)]
+    pub mnemonic: Option<SecretString>,
+
+    /// Number of accounts to derive.
+    #[arg(long, env = "POOL_SIZE", default_value_t = 1)]
+    pub pool_size: u32,
+
+    /// Index of the first derived account.
+    #[arg(long, env = "POOL_START_INDEX", default_value_t = 0)]
+    pub pool_start_index: u32,
+
+    /// BIP-32 path prefix, the account index is appended.
+    #[arg(long, env = "DERIVATION_PATH", default_value = DEFAULT_DERIVATION_PATH)]
+    pub derivation_path: String,
+}
+
+/// Fee and gas settings for `load`; prices are in gwei.
+#[derive(Debug, Args)]
+pub struct FeeArgs {
+    /// `legacy`, `eip1559` or `fee-history`.
+    #[arg(long, env = "FEE_STRATEGY", default_value = "legacy")]
+    pub fee_strategy: FeeStrategyKind,
+
+    /// Fixed legacy gas price, the base fee times the multiplier if unset.
+    #[arg(long, env = "GAS_PRICE", value_parser = parse_gwei)]
+    pub gas_price: Option<U256>,
+
+    /// Fixed EIP-1559 max fee, the base fee times the multiplier plus the priority fee if unset.
+    #[arg(long, env = "MAX_FEE", value_parser = parse_gwei)]
+    pub max_fee: Option<U256>,
+
+    /// EIP-1559 priority fee, `eth_maxPriorityFeePerGas` or zero if unset.
+    #[arg(long, env = "PRIORITY_FEE", value_parser = parse_gwei)]
+    pub priority_fee: Option<U256>,
+
+    #[arg(long, env = "BASE_FEE_MULTIPLIER", default_value_t = 2.0)]
+    pub base_fee_multiplier: f64,
+
+    /// Blocks of `eth_feeHistory` the priority fee is taken from.
+    #[arg(long, env = "FEE_HISTORY_BLOCKS", default_value_t = 20)]
+    pub fee_history_blocks: u64,
+
+    /// Reward percentile of `eth_feeHistory`.
+    #[arg(long, env = "FEE_HISTORY_PERCENTILE", default_value_t = 50.0)]
+    pub fee_history_percentile: f64,
+
+    /// How often fees follow the base fee during a run.
+    #[arg(long, env = "FEE_REFRESH_INTERVAL", value_parser = parse_duration, default_value = "5s")]
+    pub fee_refresh_interval: Duration,
+
+    #[arg(long, env = "GAS_LIMIT", default_value_t = 3_000_000)]
+    pub gas_limit: u64,
+
+    /// Estimate the gas of every tx instead of using --gas-limit.
+    #[arg(long, env = "ESTIMATE_GAS")]
+    pub estimate_gas: bool,
+
+    /// Multiplier on the estimated gas.
+    #[arg(long, env = "GAS_MARGIN", default_value_t = 1.2)]
+    pub gas_margin: f64,
+}
+
+/// How `load` recovers from rejected submissions.
+#[derive(Debug, Args)]
+pub struct SubmitArgs {
+    /// Retries of one tx across all recoveries.
+    #[arg(long, env = "SUBMIT_RETRIES", default_value_t = 3)]
+    pub submit_retries: u32,
+
+    /// First backoff after a full txpool, a rate limit or a node error, doubled on every retry.
+    #[arg(long, env = "SUBMIT_BACKOFF", value_parser = parse_duration, default_value = "500ms")]
+    pub submit_backoff: Duration,
+
+    /// Fee increase of a retry after an underpriced rejection, in percent.
+    #[arg(long, env = "FEE_BUMP_PERCENT", default_value_t = 15)]
+    pub fee_bump_percent: u64,
+
+    /// Override recoveries, `<error>=<resync|bump-fee|backoff|abort|fail>`, comma separated.
+    #[arg(long, env = "ON_ERROR", value_delimiter = ',')]
+    pub on_error: Vec<PolicyOverride>,
+
+    /// How often the nonce supervisor compares handed out nonces with the node's.
+    #[arg(long, env = "NONCE_CHECK_INTERVAL", value_parser = parse_duration, default_value = "5s")]
+    pub nonce_check_interval: Duration,
+
+    /// Re-send the lowest pending tx with bumped fees once no tx of its account was mined for this long.
+    #[arg(long, env = "STUCK_AFTER", value_parser = parse_duration, default_value = "30s")]
+    pub stuck_after: Duration,
+
+    /// Replace every tx still pending this long after it was sent, off if unset.
+    #[arg(long, env = "INCLUSION_DEADLINE", value_parser = parse_duration)]
+    pub inclusion_deadline: Option<Duration>,
+
+    /// What happens to a tx past the inclusion deadline: `speed-up` or `cancel`.
+    #[arg(long, env = "ON_DEADLINE", default_value = "speed-up")]
+    pub on_deadline: ReplaceAction,
+
+    /// Replacements of one nonce before it is left alone.
+    #[arg(long, env = "MAX_REPLACEMENTS", default_value_t = 3)]
+    pub max_replacements: u32,
+
+    /// Leave nonce gaps and stuck txs alone.
+    #[arg(long, env = "NO_NONCE_SUPERVISOR")]
+    pub no_nonce_supervisor: bool,
+}
+
+impl SubmitArgs {
+    pub fn recovery_policy(&self) -> RecoveryPolicy {
+        RecoveryPolicy {
+            max_retries: self.submit_retries,
+            backoff: self.submit_backoff,
+            fee_bump_percent: self.fee_bump_percent,
+            ..Default::default()
+        }
+        .with_overrides(&self.on_error)
+    }
+
+    /// `None` with `--no-nonce-supervisor`.
+    pub fn supervisor_config(&self) -> Option<SupervisorConfig> {
+        (!self.no_nonce_supervisor).then_some(SupervisorConfig {
+            interval: self.nonce_check_interval,
+            stuck_after: self.stuck_after,
+            inclusion_deadline: self.inclusion_deadline,
+            on_deadline: self.on_deadline,
+            max_replacements: self.max_replacements,
+            fee_bump_percent: self.fee_bump_percent,
+        })
+    }
+}
+
+impl FeeArgs {
+    pub fn fee_config(&self) -> anyhow::Result<FeeConfig> {
+        let strategy = match self.fee_strategy {
+            FeeStrategyKind::Legacy => FeeStrategy::Legacy {
+                gas_price: self.gas_price,
+            },
+            FeeStrategyKind::Eip1559 => {
+                if let (Some(max_fee), Some(priority_fee)) = (self.max_fee, self.priority_fee) {
+                    if priority_fee > max_fee {
+                        anyhow::bail!("--priority-fee exceeds --max-fee");
+                    }
+                }
+                FeeStrategy::Eip1559 {
+                    max_fee: self.max_fee,
+                    priority_fee: self.priority_fee,
+                }
+            }
+            FeeStrategyKind::FeeHistory => {
+                if !(0.0..=100.0).contains(&self.fee_history_percentile) {
+                    anyhow::bail!("--fee-history-percentile must be within 0..=100");
+                }
+                FeeStrategy::FeeHistory {
+                    blocks: self.fee_history_blocks.max(1),
+                    percentile: self.fee_history_percentile,
+                }
+            }
+        };
+        if self.base_fee_multiplier <= 0.0 {
+            anyhow::bail!("--base-fee-multiplier must be positive");
+        }
+        let gas = if self.estimate_gas {
+            GasPolicy::Estimate {
+                margin: self.gas_margin.max(1.0),
+            }
+        } else {
+            GasPolicy::Fixed(self.gas_limit)
+        };
+        Ok(FeeConfig {
+            strategy,
+            base_fee_multiplier: self.base_fee_multiplier,
+            refresh_interval: self.fee_refresh_interval,
+            gas,
+        })
+    }
+}
+
+impl LoadArgs {
+    /// The preset with the individual flags applied on top. A profile of
+    /// the config file is merged into those flags by [`parse`].
+    pub fn load_profile(&self) -> anyhow::Result<LoadProfile> {
+        let preset = match self.preset {
+            Some(preset) => preset,
+            None => self.profile.parse().unwrap_or(LoadPreset::Smoke),
+        };
+        let mut profile = LoadProfile::preset(preset);
+        if let Some(total) = self.tx_count {
+            profile.total_txs = Some(total);
+        }
+        if let Some(tps) = self.tps {
+            profile.tps = Some(tps);
+            profile.stages.clear();
+        }
+        if let Some(duration) = self.duration {
+            profile.duration = Some(duration);
+        }
+        if !self.stages.is_empty() {
+            profile.stages = self.stages.clone();
+        }
+        if let Some(burst) = self.burst {
+            profile.burst = Some(burst);
+            profile.stages.clear();
+        }
+        profile.validate()?;
+        Ok(profile)
+    }
+
+    pub fn tracker_config(&self) -> TrackerConfig {
+        TrackerConfig {
+            confirmations: self.confirmations.max(1),
+            timeout: self.receipt_timeout,
+            poll_interval: self.receipt_poll_interval,
+        }
+    }
+
+    pub fn in_flight_window(&self) -> InFlightWindow {
+        InFlightWindow {
+            per_account: self.max_in_flight_per_account.max(1),
+            global: self.max_in_flight,
+        }
+    }
+
+    pub fn export_config(&self) -> Option<ExportConfig> {
+        self.output_dir.clone().map(|dir| ExportConfig {
+            dir,
+            format: self.output_format,
+        })
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct ReadArgs {
+    /// Read at this block instead of the latest one.
+    #[arg(long)]
+    pub block: Option<u64>,
+}
+
+#[derive(Debug, Args)]
+pub struct WithdrawArgs {
+    /// Wait for the chain to pass the unlock time instead of failing.
+    #[arg(long)]
+    pub wait: bool,
+
+    /// Give up waiting after this long.
+    #[arg(long, value_parser = parse_duration, requires = "wait")]
+    pub max_wait: Option<Duration>,
+
+    /// How often the chain time is checked while waiting.
+    #[arg(long, value_parser = parse_duration, default_value = "5s")]
+    pub poll_interval: Duration,
+}
+
+#[derive(Debug, Args)]
+pub struct WatchArgs {
+    /// First block to backfill from, instead of the checkpoint or the deployment block.
+    #[arg(long)]
+    pub from_block: Option<u64>,
+
+    /// Stop after this block instead of following new ones.
+    #[arg(long)]
+    pub to_block: Option<u64>,
+
+    /// Blocks per `eth_getLogs`, halved while the provider rejects the range.
+    #[arg(long, env = "WATCH_CHUNK_SIZE", default_value_t = 2000)]
+    pub chunk_size: u64,
+
+    /// Blocks behind the head before events are processed.
+    #[arg(long, env = "WATCH_CONFIRMATIONS", default_value_t = 6)]
+    pub confirmations: u64,
+
+    /// How often the head is checked for new blocks.
+    #[arg(long, env = "WATCH_POLL_INTERVAL", value_parser = parse_duration, default_value = "2s")]
+    pub poll_interval: Duration,
+
+    /// Append the events to this `.jsonl` or `.csv` file.
+    #[arg(long, env = "WATCH_OUTPUT")]
+    pub output: Option<PathBuf>,
+
+    /// Where the last processed block is kept, `<deployments dir>/watch-<chain id>.json` by default.
+    #[arg(long, env = "WATCH_CHECKPOINT")]
+    pub checkpoint: Option<PathBuf>,
+
+    /// Neither resume from nor write a checkpoint.
+    #[arg(long, conflicts_with = "checkpoint")]
+    pub no_checkpoint: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct ReplaceArgs {
+    /// Nonce of the stuck tx.
+    #[arg(long)]
+    pub nonce: u64,
+
+    /// Account that sent it, the operator's by default; others are looked up in the wallet pool.
+    #[arg(long)]
+    pub address: Option<Address>,
+
+    /// `speed-up` re-sends the tx from the node's txpool with bumped fees, `cancel` replaces it with a
+    /// zero-value self-transfer.
+    #[arg(long, default_value = "speed-up")]
+    pub action: ReplaceAction,
+
+    /// Fee increase over the stuck tx, in percent.
+    #[arg(long, env = "FEE_BUMP_PERCENT", default_value_t = 15)]
+    pub fee_bump_percent: u64,
+
+    /// How long to wait for the replacement to be mined.
+    #[arg(long, value_parser = parse_duration, default_value = "2m")]
+    pub timeout: Duration,
+
+    #[command(flatten)]
+    pub pool: PoolArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct DeployArgs {
+    /// Unix timestamp from which `withdraw()` is allowed.
+    #[arg(long, conflicts_with = "unlock_in")]
+    pub unlock_time: Option<u64>,
+
+    /// Unlock this long after the latest block, when --unlock-time is not set.
+    #[arg(long, value_parser = parse_duration, default_value = "1h")]
+    pub unlock_in: Duration,
+
+    /// Ether locked in the contract.
+    #[arg(long, value_parser = parse_eth, default_value = "0")]
+    pub value: U256,
+}
+
+#[derive(Debug, Args)]
+pub struct ReportArgs {
+    /// A `.txs.jsonl` or `.txs.csv` file written by `load --output-dir`.
+    pub file: PathBuf,
+}
+
+fn parse_eth(input: &str) -> anyhow::Result<U256> {
+    Ok(parse_ether(input)?)
+}
+
+fn parse_gwei(input: &str) -> anyhow::Result<U256> {
+    Ok(parse_units(input, "gwei")?.into())
+}
+
+/// Parses the command line and fills in the network and profile it selects
+/// from the config file. Flags win over a selection, a selection over
+/// environment variables and defaults.
+pub fn parse() -> anyhow::Result<Cli> {
+    resolve(&Cli::command().get_matches())
+}
+
+fn resolve(matches: &ArgMatches) -> anyhow::Result<Cli> {
+    let mut cli = Cli::from_arg_matches(matches)?;
+    let sources = Sources::new(matches);
+    let config = cli.chain.load_config()?;
+    if let Some(name) = cli.chain.network.clone() {
+        let Some(config) = &config else {
+            bail!(
+                "--network {} needs a config file, pass --config or create {}",
+                name,
+                DEFAULT_CONFIG
+            );
+        };
+        let network = config.network(&name)?;
+        tracing::info!(
+            target: COUNTER_CLIENT,
+            "network {} chain id {}",
+            name,
+            network.chain_id
+        );
+        cli.apply_network(network, &sources)?;
+    }
+    if let Some(args) = cli.load_args_mut() {
+        match config
+            .as_ref()
+            .and_then(|config| config.profile(&args.profile))
+        {
+            Some(workload) => {
+                tracing::info!(target: COUNTER_CLIENT, "profile {}", args.profile);
+                args.apply_workload(workload, &sources)?;
+            }
+            None => {
+                if args.profile.parse::<LoadPreset>().is_err() {
+                    bail!(
+                        "unknown profile `{}`, expected `smoke`, `soak` or a profile of the config file",
+                        args.profile
+                    );
+                }
+            }
+        }
+    }
+    Ok(cli)
+}
+
+/// Where the parsed values came from, so the config file replaces defaults
+/// and environment variables but not flags.
+struct Sources<'a> {
+    /// The top level and every subcommand below it.
+    levels: Vec<&'a ArgMatches>,
+}
+
+impl<'a> Sources<'a> {
+    fn new(matches: &'a ArgMatches) -> Self {
+        let mut levels = vec![matches];
+        let mut current = matches;
+        while let Some((_, sub)) = current.subcommand() {
+            levels.push(sub);
+            current = sub;
+        }
+        Self { levels }
+    }
+
+    fn source(&self, id: &str) -> Option<ValueSource> {
+        self.levels
+            .iter()
+            .filter(|matches| matches.try_contains_id(id).is_ok())
+            .filter_map(|matches| matches.value_source(id))
+            .max()
+    }
+
+    /// Replaces `field` with the config value unless `--<id>` was passed.
+    fn set<T>(&self, id: &str, field: &mut T, value: T) {
+        match self.source(id) {
+            Some(ValueSource::CommandLine) => return,
+            Some(ValueSource::EnvVariable) => tracing::warn!(
+                target: COUNTER_CLIENT,
+                "--{} from the environment is overridden by the config file",
+                id.replace('_', "-")
+            ),
+            _ => {}
+        }
+        *field = value;
+    }
+
+    /// [`Sources::set`] for a setting the config file may leave out.
+    fn set_some<T>(&self, id: &str, field: &mut T, value: Option<T>) {
+        if let Some(value) = value {
+            self.set(id, field, value);
+        }
+    }
+}
+
+impl Cli {
+    /// Takes the endpoints, chain id and contract of `network`, and its
+    /// block time and fees where they apply. Its address replaces the
+    /// contract of another network, `None` leaves the `deploy` record of the
+    /// chain.
+    fn apply_network(&mut self, network: &Network, sources: &Sources) -> anyhow::Result<()> {
+        let chain = &mut self.chain;
+        sources.set("rpc_url", &mut chain.rpc_url, network.rpc_urls.clone());
+        sources.set(
+            "submit_rpc_url",
+            &mut chain.submit_rpc_url,
+            network.submit_rpc_urls.clone(),
+        );
+        sources.set("chain_id", &mut chain.chain_id, Some(network.chain_id));
+        sources.set("contract", &mut chain.contract, network.contract);
+
+        let block_time = network
+            .block_time
+            .as_deref()
+            .map(parse_duration)
+            .transpose()?;
+        if let Some(Command::Watch(args)) = &mut self.command {
+            sources.set_some("poll_interval", &mut args.poll_interval, block_time);
+        }
+        let Some(args) = self.load_args_mut() else {
+            return Ok(());
+        };
+        sources.set_some(
+            "receipt_poll_interval",
+            &mut args.receipt_poll_interval,
+            block_time,
+        );
+        let fees = &network.fees;
+        let args = &mut args.fees;
+        sources.set_some(
+            "fee_strategy",
+            &mut args.fee_strategy,
+            fees.strategy.as_deref().map(str::parse).transpose()?,
+        );
+        sources.set_some(
+            "gas_price",
+            &mut args.gas_price,
+            gwei(fees.gas_price)?.map(Some),
+        );
+        sources.set_some("max_fee", &mut args.max_fee, gwei(fees.max_fee)?.map(Some));
+        sources.set_some(
+            "priority_fee",
+            &mut args.priority_fee,
+            gwei(fees.priority_fee)?.map(Some),
+        );
+        sources.set_some(
+            "base_fee_multiplier",
+            &mut args.base_fee_multiplier,
+            fees.base_fee_multiplier,
+        );
+        sources.set_some("gas_limit", &mut args.gas_limit, fees.gas_limit);
+        sources.set_some("estimate_gas", &mut args.estimate_gas, fees.estimate_gas);
+        Ok(())
+    }
+}
+
+impl LoadArgs {
+    /// Takes the load shape of `workload` whole, a rate left in the
+    /// environment would override its stages, and its other settings where
+    /// it has them.
+    fn apply_workload(&mut self, workload: &Workload, sources: &Sources) -> anyhow::Result<()> {
+        sources.set(
+            "preset",
+            &mut self.preset,
+            workload.preset.as_deref().map(str::parse).transpose()?,
+        );
+        sources.set("tx_count", &mut self.tx_count, workload.tx_count);
+        sources.set("tps", &mut self.tps, workload.tps);
+        sources.set(
+            "duration",
+            &mut self.duration,
+            workload
+                .duration
+                .as_deref()
+                .map(parse_duration)
+                .transpose()?,
+        );
+        sources.set(
+            "stages",
+            &mut self.stages,
+            workload
+                .stages
+                .iter()
+                .map(|stage| stage.parse())
+                .collect::<anyhow::Result<_>>()?,
+        );
+        sources.set(
+            "burst",
+            &mut self.burst,
+            workload.burst.as_deref().map(str::parse).transpose()?,
+        );
+        sources.set_some("pool_size", &mut self.pool.pool_size, workload.pool_size);
+        sources.set_some(
+            "max_in_flight_per_account",
+            &mut self.max_in_flight_per_account,
+            workload.max_in_flight_per_account,
+        );
+        sources.set_some(
+            "max_in_flight",
+            &mut self.max_in_flight,
+            workload.max_in_flight.map(Some),
+        );
+        sources.set_some(
+            "confirmations",
+            &mut self.confirmations,
+            workload.confirmations,
+        );
+        sources.set_some(
+            "receipt_timeout",
+            &mut self.receipt_timeout,
+            workload
+                .receipt_timeout
+                .as_deref()
+                .map(parse_duration)
+                .transpose()?,
+        );
+        Ok(())
+    }
+}
+
+/// A config file price in gwei.
+fn gwei(value: Option<f64>) -> anyhow::Result<Option<U256>> {
+    value.map(|gwei| parse_gwei(&gwei.to_string())).transpose()
+}
+
+/// Lets `.env` files written for older versions keep working. Writes the
+/// environment, so it runs before the runtime starts its threads.
+pub fn apply_legacy_env() {
+    if env::var_os("CONTRACT_ADDR").is_none() {
+        if let Some(addr) = env::var_os("CONCTRACT_ADDR") {
+            env::set_var("CONTRACT_ADDR", addr);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    const CONFIG: &str = r#"
+[networks.test]
+rpc_urls = ["http://a:8545", "http://b:8545"]
+chain_id = 4460
+contract = "0x2222222222222222222222222222222222222222"
+block_time = "2s"
+
+[networks.test.fees]
+strategy = "legacy"
+gas_price = 0.5
+
+[profiles.wide]
+preset = "soak"
+tx_count = 40
+pool_size = 8
+receipt_timeout = "2m"
+"#;
+
+    /// Writes [`CONFIG`] to a file of its own for the test `name`.
+    fn config(name: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("counter-cli-{}-{}.toml", std::process::id(), name));
+        fs::write(&path, CONFIG).unwrap();
+        path
+    }
+
+    fn parse_args(name: &str, args: &[&str]) -> anyhow::Result<Cli> {
+        let config = config(name);
+        let mut argv = vec!["ethers-counter"];
+        argv.extend_from_slice(args);
+        argv.extend(["--config", config.to_str().unwrap()]);
+        let matches = Cli::command().try_get_matches_from(argv)?;
+        let cli = resolve(&matches);
+        fs::remove_file(config).unwrap();
+        cli
+    }
+
+    #[test]
+    fn network_fills_in_the_chain_settings() {
+        let mut cli = parse_args("network", &["--network", "test"]).unwrap();
+        assert_eq!(cli.chain.rpc_url, ["http://a:8545", "http://b:8545"]);
+        assert_eq!(cli.chain.chain_id, Some(4460));
+        assert_eq!(cli.chain.contract, Some(Address::repeat_byte(0x22)));
+        let load = cli.load_args_mut().unwrap();
+        assert_eq!(load.receipt_poll_interval, Duration::from_secs(2));
+        assert_eq!(load.fees.fee_strategy, FeeStrategyKind::Legacy);
+        assert_eq!(load.fees.gas_price, Some(U256::from(500_000_000)));
+    }
+
+    #[test]
+    fn flags_win_over_the_network() {
+        let cli = parse_args(
+            "network-flags",
+            &[
+                "watch",
+                "--network",
+                "test",
+                "--rpc-url",
+                "http://c:8545",
+                "--poll-interval",
+                "500ms",
+            ],
+        )
+        .unwrap();
+        assert_eq!(cli.chain.rpc_url, ["http://c:8545"]);
+        assert_eq!(cli.chain.chain_id, Some(4460));
+        let Some(Command::Watch(args)) = cli.command else {
+            panic!("expected watch");
+        };
+        assert_eq!(args.poll_interval, Duration::from_millis(500));
+    }
+
+    #[test]
+    fn network_sets_the_watch_poll_interval() {
+        let cli = parse_args("network-watch", &["watch", "--network", "test"]).unwrap();
+        let Some(Command::Watch(args)) = cli.command else {
+            panic!("expected watch");
+        };
+        assert_eq!(args.poll_interval, Duration::from_secs(2));
+    }
+
+    #[test]
+    fn profile_fills_in_the_load_settings() {
+        let mut cli = parse_args("profile", &["load", "--profile", "wide"]).unwrap();
+        let load = cli.load_args_mut().unwrap();
+        assert_eq!(load.preset, Some(LoadPreset::Soak));
+        assert_eq!(load.tx_count, Some(40));
+        assert_eq!(load.pool.pool_size, 8);
+        assert_eq!(load.receipt_timeout, Duration::from_secs(120));
+    }
+
+    #[test]
+    fn flags_win_over_the_profile() {
+        let mut cli = parse_args(
+            "profile-flags",
+            &["--profile", "wide", "--tx-count", "5", "--pool-size", "2"],
+        )
+        .unwrap();
+        let load = cli.load_args_mut().unwrap();
+        assert_eq!(load.preset, Some(LoadPreset::Soak));
+        assert_eq!(load.tx_count, Some(5));
+        assert_eq!(load.pool.pool_size, 2);
+    }
+
+    #[test]
+    fn presets_need_no_profile() {
+        let mut cli = parse_args("preset", &["--profile", "soak"]).unwrap();
+        assert_eq!(cli.load_args_mut().unwrap().profile, "soak");
+    }
+
+    #[test]
+    fn rejects_unknown_selections() {
+        let err = parse_args("unknown-profile", &["--profile", "nope"]).unwrap_err();
+        assert!(
+            err.to_string().contains("unknown profile `nope`"),
+            "{}",
+            err
+        );
+        let err = parse_args("unknown-network", &["--network", "nope"]).unwrap_err();
+        assert!(
+            err.to_string().contains("unknown network `nope`"),
+            "{}",
+            err
+        );
+    }
+}
@@ -0,0 +1,269 @@
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail};
+use ethers::{
+    contract::parse_log,
+    providers::{Middleware, Provider},
+    types::{Address, BlockId, BlockNumber, TransactionReceipt, U256},
+};
+
+use crate::{
+    bindings::lock::{Lock, WithdrawalFilter},
+    chain::{ChainCapabilities, ChainGuard},
+    connect_evm_rpcs,
+    endpoints::EndpointConfig,
+    get_signer_provider, revert,
+    signer::KeySource,
+    split_evm_rpc,
+    transport::Transport,
+    watch::{Checkpoint, WatchConfig, WithdrawalWatcher},
+    SignerProvider, COUNTER_CLIENT,
+};
+
+/// Where and as whom to connect.
+#[derive(Debug, Clone)]
+pub struct ClientConfig {
+    /// One or more endpoints of the same chain, see [`connect_evm_rpcs`].
+    pub rpc_urls: Vec<String>,
+    /// Send transactions here instead, e.g. the sequencer, while `rpc_urls`
+    /// serve reads. Empty to send through `rpc_urls` too.
+    pub submit_rpc_urls: Vec<String>,
+    pub endpoint: EndpointConfig,
+    pub key: KeySource,
+    pub guard: ChainGuard,
+}
+
+/// Provider stack and operator signer of one chain, everything but the
+/// contract.
+pub struct Connection {
+    pub provider: Provider<Transport>,
+    pub chain: ChainCapabilities,
+    pub signer: Arc<SignerProvider>,
+}
+
+impl Connection {
+    /// Connects the endpoints, checks with the guard that all of them are on
+    /// a chain the key may sign for and probes what the chain supports.
+    pub async fn connect(config: &ClientConfig) -> anyhow::Result<Self> {
+        let operator = config.key.signer().await?;
+        let mut provider = connect_evm_rpcs(&config.rpc_urls, config.endpoint.clone()).await?;
+        if !config.submit_rpc_urls.is_empty() {
+            let submit = connect_evm_rpcs(&config.submit_rpc_urls, config.endpoint.clone()).await?;
+            provider = split_evm_rpc(provider, submit);
+        }
+        config.guard.verify(&provider).await?;
+        let chain = ChainCapabilities::probe(&provider).await?;
+        tracing::info!(target: COUNTER_CLIENT, "{}", chain);
+        let signer = Arc::new(get_signer_provider(provider.clone(), chain.chain_id, operator));
+        Ok(Self {
+            provider,
+            chain,
+            signer,
+        })
+    }
+
+    /// Address the signer stack sends from.
+    pub fn sender(&self) -> Address {
+        self.signer.inner().address()
+    }
+}
+
+/// A `Lock` contract on one chain, read and driven through the operator
+/// signer.
+///
+/// ```no_run
+/// # async fn example() -> anyhow::Result<()> {
+/// use ethers_counter::{
+///     chain::ChainGuard,
+///     client::{ClientConfig, CounterClient},
+///     endpoints::EndpointConfig,
+///     signer::KeySource,
+/// };
+///
+/// let config = ClientConfig {
+///     rpc_urls: vec!["http://127.0.0.1:8545".to_string()],
+///     submit_rpc_urls: vec![],
+///     endpoint: EndpointConfig::default(),
+///     key: KeySource::PrivateKey(std::env::var("PRIVATE_KEY")?.into()),
+///     guard: ChainGuard {
+///         expected_chain_id: Some(4460),
+///         allow_mainnet: false,
+///     },
+/// };
+/// let client = CounterClient::connect(&config, "0xDdDd6D77fDdD65A5344bFa1F670BbBB770d3d908".parse()?).await?;
+/// let before = client.counter(None).await?;
+/// client.inc().await?;
+/// assert_eq!(client.counter(None).await?, before + 1);
+/// # Ok(())
+/// # }
+/// ```
+pub struct CounterClient {
+    pub provider: Provider<Transport>,
+    pub chain_id: u64,
+    pub chain: ChainCapabilities,
+    pub signer: Arc<SignerProvider>,
+    pub contract: Lock<SignerProvider>,
+}
+
+impl CounterClient {
+    pub async fn connect(config: &ClientConfig, contract: Address) -> anyhow::Result<Self> {
+        Ok(Self::new(Connection::connect(config).await?, contract))
+    }
+
+    pub fn new(connection: Connection, contract: Address) -> Self {
+        let Connection {
+            provider,
+            chain,
+            signer,
+        } = connection;
+        Self {
+            provider,
+            chain_id: chain.chain_id,
+            chain,
+            contract: Lock::new(contract, signer.clone()),
+            signer,
+        }
+    }
+
+    /// Address the signer stack sends from.
+    pub fn sender(&self) -> Address {
+        self.signer.inner().address()
+    }
+
+    /// The counter at `block`, the latest one if `None`.
+    pub async fn counter(&self, block: Option<u64>) -> anyhow::Result<U256> {
+        let mut call = self.contract.counter();
+        if let Some(block) = block {
+            call = call.block(block);
+        }
+        Ok(call.call().await?)
+    }
+
+    /// Sends `inc()` and waits for it to be mined. Fails with the decoded
+    /// revert reason if the node rejects it or it reverts.
+    pub async fn inc(&self) -> anyhow::Result<TransactionReceipt> {
+        let mut call = self.contract.inc();
+        if !self.chain.london {
+            call = call.legacy();
+        }
+        let pending = call.send().await.map_err(|err| {
+            anyhow!("inc tx failed, {}", revert::describe_contract_error(&err))
+        })?;
+        tracing::info!(target: COUNTER_CLIENT, "inc tx: {:?}", *pending);
+        let Some(receipt) = pending.await? else {
+            bail!("inc tx was dropped");
+        };
+        self.check_receipt("inc", &receipt).await?;
+        Ok(receipt)
+    }
+
+    /// Seconds until `withdraw()` is allowed by the time of the latest
+    /// block, `None` once it is. An unlock time beyond `u64` seconds away
+    /// saturates to `u64::MAX`.
+    pub async fn locked_for(&self) -> anyhow::Result<Option<u64>> {
+        let unlock_time = self.contract.unlock_time().call().await?;
+        let latest = self
+            .provider
+            .get_block(BlockId::Number(BlockNumber::Latest))
+            .await?
+            .ok_or_else(|| anyhow!("the node returned no latest block"))?;
+        if latest.timestamp >= unlock_time {
+            return Ok(None);
+        }
+        let remaining = unlock_time - latest.timestamp;
+        Ok(Some(u64::try_from(remaining).unwrap_or(u64::MAX)))
+    }
+
+    /// Fails unless the sender owns the lock, the only account `withdraw()`
+    /// accepts.
+    pub async fn ensure_owner(&self) -> anyhow::Result<()> {
+        let owner = self.contract.owner().call().await?;
+        if owner != self.sender() {
+            bail!(
+                "{:?} is not the owner of the lock, {:?} is",
+                self.sender(),
+                owner
+            );
+        }
+        Ok(())
+    }
+
+    /// Calls `withdraw()` as the owner once unlocked and returns the
+    /// `Withdrawal` it emitted with its receipt. The call is checked with
+    /// `eth_call` first, a failure reports the contract's reason without
+    /// paying for a revert.
+    pub async fn withdraw(&self) -> anyhow::Result<(WithdrawalFilter, TransactionReceipt)> {
+        let contract = &self.contract;
+        self.ensure_owner().await?;
+        if let Some(remaining) = self.locked_for().await? {
+            bail!("locked for another {}s", remaining);
+        }
+
+        let mut call = contract.withdraw();
+        if !self.chain.london {
+            call = call.legacy();
+        }
+        call.call().await.map_err(|err| {
+            anyhow!(
+                "withdraw() would fail, {}",
+                revert::describe_contract_error(&err)
+            )
+        })?;
+        let pending = call.send().await.map_err(|err| {
+            anyhow!(
+                "withdraw tx failed, {}",
+                revert::describe_contract_error(&err)
+            )
+        })?;
+        tracing::info!(target: COUNTER_CLIENT, "withdraw tx: {:?}", *pending);
+        let Some(receipt) = pending.await? else {
+            bail!("withdraw tx was dropped");
+        };
+        self.check_receipt("withdraw", &receipt).await?;
+        let withdrawal = receipt
+            .logs
+            .iter()
+            .filter(|log| log.address == contract.address())
+            .find_map(|log| parse_log::<WithdrawalFilter>(log.clone()).ok())
+            .ok_or_else(|| {
+                anyhow!(
+                    "withdraw tx {:?} emitted no Withdrawal",
+                    receipt.transaction_hash
+                )
+            })?;
+        Ok((withdrawal, receipt))
+    }
+
+    /// Follows the `Withdrawal` events of the contract from `from_block`. The
+    /// `checkpoint` of an earlier run is checked to still be canonical, the
+    /// watcher rewinds past it if not.
+    pub fn watch(
+        &self,
+        from_block: u64,
+        checkpoint: Option<Checkpoint>,
+        config: WatchConfig,
+    ) -> WithdrawalWatcher {
+        WithdrawalWatcher::new(
+            self.provider.clone(),
+            self.chain_id,
+            self.contract.address(),
+            from_block,
+            checkpoint,
+            config,
+        )
+    }
+
+    async fn check_receipt(&self, call: &str, receipt: &TransactionReceipt) -> anyhow::Result<()> {
+        if receipt.status.is_some_and(|status| status.as_u64() == 0) {
+            let reason = revert::describe_receipt(&self.provider, receipt).await;
+            bail!(
+                "{} tx {:?} in block {:?} {}",
+                call,
+                receipt.transaction_hash,
+                receipt.block_number,
+                reason
+            );
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,89 @@
+use ethers_counter::config::redact_url;
+
+use crate::cli::{ChainArgs, LoadArgs};
+
+pub fn run(chain: &ChainArgs, args: &LoadArgs) -> anyhow::Result<()> {
+    let config = chain.load_config()?;
+    let network = match (&config, &chain.network) {
+        (Some(config), Some(name)) => Some((name, config.network(name)?)),
+        _ => None,
+    };
+    let load_profile = args.load_profile()?;
+    let fees = args.fees.fee_config()?;
+
+    match chain.config_path() {
+        Some(path) => println!("config file:   {}", path.display()),
+        None => println!("config file:   none"),
+    }
+    match network {
+        Some((name, network)) => println!(
+            "network:       {} chain id {} block time {}",
+            name,
+            network.chain_id,
+            network.block_time.as_deref().unwrap_or("unknown")
+        ),
+        None => println!("network:       none"),
+    }
+    match chain.chain_id {
+        Some(chain_id) => println!("chain id:      {} required", chain_id),
+        None => println!("chain id:      any"),
+    }
+    println!(
+        "mainnet:       {}",
+        if chain.allow_mainnet {
+            "allowed"
+        } else {
+            "refused"
+        }
+    );
+    println!("rpc urls:      {}", redact_urls(&chain.rpc_url));
+    println!("submit urls:   {}", redact_urls(&chain.submit_rpc_url));
+    match chain.contract {
+        Some(contract) => println!("contract:      {:?}", contract),
+        None => println!(
+            "contract:      the deploy record in {}",
+            chain.deployments_dir.display()
+        ),
+    }
+    match chain.key_source() {
+        Ok(key) => println!("operator key:  {:?}", key),
+        Err(_) => println!("operator key:  unset"),
+    }
+    println!("profile:       {} ({})", args.profile, load_profile);
+    match &args.pool.mnemonic {
+        Some(_) => println!(
+            "senders:       {} accounts from index {} of mnemonic <redacted>",
+            args.pool.pool_size, args.pool.pool_start_index
+        ),
+        None => println!("senders:       the operator key"),
+    }
+    let window = args.in_flight_window();
+    println!(
+        "in flight:     {} per account, {} overall",
+        window.per_account,
+        window
+            .global
+            .map_or_else(|| "unbounded".to_string(), |global| global.to_string())
+    );
+    println!(
+        "fees:          {:?} x{}",
+        fees.strategy, fees.base_fee_multiplier
+    );
+    println!("gas:           {:?}", fees.gas);
+    let tracker = args.tracker_config();
+    println!(
+        "receipts:      {} confirmations, timeout {:?}, poll {:?}",
+        tracker.confirmations, tracker.timeout, tracker.poll_interval
+    );
+    Ok(())
+}
+
+fn redact_urls(urls: &[String]) -> String {
+    if urls.is_empty() {
+        return "none".to_string();
+    }
+    urls.iter()
+        .map(|url| redact_url(url))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
@@ -0,0 +1,100 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context as _};
+use ethers::{
+    providers::Middleware,
+    types::{Address, BlockId, BlockNumber, TxHash, U256},
+    utils::format_ether,
+};
+use serde::{Deserialize, Serialize};
+
+use ethers_counter::{bindings::lock::Lock, Connection, COUNTER_CLIENT};
+
+use crate::cli::{ChainArgs, DeployArgs};
+
+/// A deployed `Lock`, persisted per chain so later runs find it without
+/// `CONTRACT_ADDR`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Deployment {
+    pub chain_id: u64,
+    pub address: Address,
+    pub tx_hash: TxHash,
+    pub block_number: Option<u64>,
+    pub deployer: Address,
+    pub unlock_time: U256,
+    pub value: U256,
+}
+
+impl Deployment {
+    pub fn path(dir: &Path, chain_id: u64) -> PathBuf {
+        dir.join(format!("lock-{}.json", chain_id))
+    }
+
+    pub fn save(&self, dir: &Path) -> anyhow::Result<PathBuf> {
+        fs::create_dir_all(dir)?;
+        let path = Self::path(dir, self.chain_id);
+        fs::write(&path, serde_json::to_string_pretty(self)?)?;
+        Ok(path)
+    }
+
+    /// `None` when nothing was deployed to this chain from `dir`.
+    pub fn load(dir: &Path, chain_id: u64) -> anyhow::Result<Option<Self>> {
+        let path = Self::path(dir, chain_id);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let deployment = serde_json::from_str(&fs::read_to_string(&path)?)
+            .with_context(|| format!("reading {}", path.display()))?;
+        Ok(Some(deployment))
+    }
+}
+
+pub async fn run(chain_args: &ChainArgs, args: DeployArgs) -> anyhow::Result<()> {
+    let Connection {
+        provider,
+        chain,
+        signer,
+    } = Connection::connect(&chain_args.client_config()?).await?;
+
+    // The constructor requires an unlock time past the deploying block.
+    let unlock_time = match args.unlock_time {
+        Some(unlock_time) => U256::from(unlock_time),
+        None => {
+            let latest = provider
+                .get_block(BlockId::Number(BlockNumber::Latest))
+                .await?
+                .ok_or_else(|| anyhow!("the node returned no latest block"))?;
+            latest.timestamp + args.unlock_in.as_secs()
+        }
+    };
+
+    let mut deployer = Lock::deploy(signer.clone(), unlock_time)?.value(args.value);
+    if !chain.london {
+        deployer = deployer.legacy();
+    }
+    tracing::info!(
+        target: COUNTER_CLIENT,
+        "deploying Lock with unlock time {} and {} ether",
+        unlock_time,
+        format_ether(args.value)
+    );
+    let (contract, receipt) = deployer.send_with_receipt().await?;
+
+    let deployment = Deployment {
+        chain_id: chain.chain_id,
+        address: contract.address(),
+        tx_hash: receipt.transaction_hash,
+        block_number: receipt.block_number.map(|n| n.as_u64()),
+        deployer: signer.inner().address(),
+        unlock_time,
+        value: args.value,
+    };
+    let path = deployment.save(&chain_args.deployments_dir)?;
+    println!("Lock deployed to {:?} in tx {:?}", deployment.address, deployment.tx_hash);
+    println!("saved to {}", path.display());
+    println!("CONTRACT_ADDR={:?}", deployment.address);
+    Ok(())
+}
@@ -0,0 +1,151 @@
+use std::sync::Arc;
+
+use tokio::{sync::mpsc, time::Instant};
+
+use ethers_counter::{
+    endpoints::EndpointReport,
+    export,
+    fees::FeeOracle,
+    inclusion::InclusionWatcher,
+    lag::ReplicaLagProbe,
+    load::Scheduler,
+    stats::RunStats,
+    supervisor::NonceSupervisor,
+    tracker::{Replacements, TxTracker},
+    verify::{CounterCheck, CounterSnapshot},
+    wallet_pool::WalletPool,
+    workload::IncWorkload,
+    CounterClient, COUNTER_CLIENT,
+};
+
+use crate::cli::{LoadArgs, PoolArgs};
+use ethers::{providers::Middleware, types::U256};
+
+pub async fn run(client: &CounterClient, args: LoadArgs) -> anyhow::Result<()> {
+    let profile = args.load_profile()?;
+    let export_config = args.export_config();
+
+    let fees = FeeOracle::new(client.provider.clone(), client.chain, args.fees.fee_config()?).await?;
+
+    let contract = &client.contract;
+    let start_block = client.provider.get_block_number().await?.as_u64();
+    let start = CounterSnapshot::read(contract, start_block).await?;
+    tracing::info!(target: COUNTER_CLIENT, "counter start value: {}", start.value);
+
+    let pool = wallet_pool(client, &args.pool, args.fund_amount).await?;
+    tracing::info!(
+        target: COUNTER_CLIENT,
+        "load profile: {} accounts: {}",
+        profile,
+        pool.len()
+    );
+    let policy = args.submit.recovery_policy();
+    tracing::info!(target: COUNTER_CLIENT, "on submit errors: {}", policy);
+    let workload = Arc::new(IncWorkload::new(
+        contract.address(),
+        &pool,
+        fees.clone(),
+        args.in_flight_window(),
+        policy,
+    ));
+    let replacements = Replacements::default();
+    // Runs until the tracker settled, stuck txs hold up inclusion.
+    let supervisor = args.submit.supervisor_config().map(|config| {
+        NonceSupervisor::spawn(
+            workload.nonce_allocators(),
+            fees.clone(),
+            replacements.clone(),
+            config,
+        )
+    });
+    let refresher = fees.spawn_refresh();
+    let watcher = InclusionWatcher::spawn(&client.provider, args.subscribe_pending);
+    if watcher.is_none() && args.subscribe_pending {
+        tracing::warn!(target: COUNTER_CLIENT, "--subscribe-pending needs a ws or ipc endpoint");
+    }
+    let mut tracker = TxTracker::new(client.provider.clone(), args.tracker_config())
+        .with_watcher(watcher)
+        .with_replacements(replacements);
+    let lag_probe = ReplicaLagProbe::spawn(client.provider.as_ref(), args.lag_poll_interval);
+    let send_started = Instant::now();
+    let mut scheduler = Scheduler::new(profile);
+    let (sent_tx, sent_rx) = mpsc::unbounded_channel();
+    let (sent, ()) = tokio::join!(
+        workload.clone().run(&mut scheduler, sent_tx),
+        tracker.follow(sent_rx)
+    );
+    let send_finished = Instant::now();
+    refresher.abort();
+    sent?;
+    tracing::info!(target: COUNTER_CLIENT, "sent {} txs", tracker.txs().len());
+
+    tracker.settle().await?;
+    let supervisor_report = supervisor.map(|supervisor| supervisor.report());
+    tracker.log_outcome();
+    let stats = RunStats::collect(tracker.txs(), send_started, send_finished);
+    // Pin the end read to the last inclusion so later foreign txs don't count.
+    let end_block = tracker
+        .txs()
+        .iter()
+        .filter_map(|tx| tx.receipt.as_ref()?.block_number)
+        .map(|block| block.as_u64())
+        .max()
+        .unwrap_or(start_block);
+    let end = CounterSnapshot::read(contract, end_block).await?;
+    tracing::info!(target: COUNTER_CLIENT, "counter finish value: {}", end.value);
+    println!("{}", stats);
+    if let Some(pool) = client.provider.as_ref().pool().filter(|pool| pool.endpoints().len() > 1) {
+        println!("{}", EndpointReport(pool));
+    }
+    if let Some(lag_probe) = lag_probe {
+        println!("{}", lag_probe.report(stats.txs_per_block.keys().copied()));
+    }
+    if let Some(report) = supervisor_report {
+        println!("{}", report);
+    }
+    if let Some(export_config) = &export_config {
+        export::export_run(export_config, &export::run_id(), tracker.txs(), &stats)?;
+    }
+
+    if let Some(kind) = workload.aborted() {
+        anyhow::bail!("run aborted on {}", kind);
+    }
+
+    let check = CounterCheck::new(start, end, tracker.txs());
+    if !check.is_ok() {
+        tracing::error!(target: COUNTER_CLIENT, "{}", check);
+        anyhow::bail!(
+            "counter invariant violated: expected delta {}, actual {}",
+            check.expected_delta,
+            check.actual_delta()
+        );
+    }
+    println!("{}", check);
+
+    Ok(())
+}
+
+async fn wallet_pool(
+    client: &CounterClient,
+    args: &PoolArgs,
+    fund_amount: U256,
+) -> anyhow::Result<WalletPool> {
+    let Some(mnemonic) = &args.mnemonic else {
+        return Ok(WalletPool::single(client.signer.clone()));
+    };
+    let pool = WalletPool::derive(
+        &client.provider,
+        client.chain_id,
+        mnemonic.expose(),
+        &args.derivation_path,
+        args.pool_start_index,
+        args.pool_size,
+    )?;
+    if pool.is_empty() {
+        anyhow::bail!("wallet pool is empty, --pool-size must be at least 1");
+    }
+    if !fund_amount.is_zero() {
+        pool.fund(&client.signer, fund_amount).await?;
+    }
+    Ok(pool)
+}
@@ -0,0 +1,32 @@
+pub mod config;
+pub mod deploy;
+pub mod load;
+pub mod read;
+pub mod replace;
+pub mod report;
+pub mod watch;
+pub mod withdraw;
+
+use ethers_counter::{Connection, CounterClient};
+
+use crate::cli::{ChainArgs, Command};
+
+/// Connects the chain subcommands to the contract they drive.
+async fn connect(args: &ChainArgs) -> anyhow::Result<CounterClient> {
+    let connection = Connection::connect(&args.client_config()?).await?;
+    let contract = args.contract(connection.chain.chain_id)?;
+    Ok(CounterClient::new(connection, contract))
+}
+
+pub async fn run(chain: ChainArgs, command: Command) -> anyhow::Result<()> {
+    match command {
+        Command::Load(args) => load::run(&connect(&chain).await?, args).await,
+        Command::Read(args) => read::run(&connect(&chain).await?, args).await,
+        Command::Withdraw(args) => withdraw::run(&connect(&chain).await?, args).await,
+        Command::Watch(args) => watch::run(&connect(&chain).await?, &chain, args).await,
+        Command::Replace(args) => replace::run(&chain, args).await,
+        Command::Deploy(args) => deploy::run(&chain, args).await,
+        Command::Report(args) => report::run(args),
+        Command::Config(args) => config::run(&chain, &args),
+    }
+}
@@ -0,0 +1,29 @@
+use ethers::{providers::Middleware, types::BlockId};
+
+use ethers_counter::CounterClient;
+
+use crate::cli::ReadArgs;
+
+pub async fn run(client: &CounterClient, args: ReadArgs) -> anyhow::Result<()> {
+    let block = match args.block {
+        Some(block) => block,
+        None => client.provider.get_block_number().await?.as_u64(),
+    };
+    let contract = &client.contract;
+    let counter = client.counter(Some(block)).await?;
+    let unlock_time = contract.unlock_time().block(block).call().await?;
+    let owner = contract.owner().block(block).call().await?;
+    let balance = client
+        .provider
+        .get_balance(contract.address(), Some(BlockId::from(block)))
+        .await?;
+
+    println!("chain id:    {}", client.chain_id);
+    println!("block:       {}", block);
+    println!("contract:    {:?}", contract.address());
+    println!("counter:     {}", counter);
+    println!("unlock time: {}", unlock_time);
+    println!("owner:       {:?}", owner);
+    println!("balance:     {} wei", balance);
+    Ok(())
+}
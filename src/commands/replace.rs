@@ -0,0 +1,158 @@
+use std::{sync::Arc, time::Duration};
+
+use anyhow::{anyhow, bail};
+use ethers::{
+    providers::{Middleware, Provider},
+    types::{transaction::eip2718::TypedTransaction, BlockId, BlockNumber, U256},
+};
+
+use ethers_counter::{
+    fees::{FeeConfig, FeeOracle, FeeStrategy, Fees, GasPolicy},
+    replace::{self, ReplaceAction, TRANSFER_GAS},
+    submit_error::SubmitError,
+    transport::Transport,
+    wallet_pool::WalletPool,
+    Connection, SignerProvider, COUNTER_CLIENT,
+};
+
+use crate::cli::{ChainArgs, ReplaceArgs};
+
+pub async fn run(chain_args: &ChainArgs, args: ReplaceArgs) -> anyhow::Result<()> {
+    let Connection {
+        provider,
+        chain,
+        signer: operator,
+    } = Connection::connect(&chain_args.client_config()?).await?;
+    let signer = signer(&provider, chain.chain_id, operator, &args)?;
+    let from = signer.inner().address();
+    let nonce = U256::from(args.nonce);
+
+    let latest = provider
+        .get_transaction_count(from, Some(BlockId::Number(BlockNumber::Latest)))
+        .await?;
+    if nonce < latest {
+        bail!(
+            "nonce {} of {:?} is already mined, the next one is {}",
+            nonce,
+            from,
+            latest
+        );
+    }
+    let original: TypedTransaction = match replace::find_pending(&provider, from, nonce).await? {
+        Some(pending) => {
+            let original = (&pending).into();
+            tracing::info!(
+                target: COUNTER_CLIENT,
+                "pending at nonce {}: {:?} with {}",
+                nonce,
+                pending.hash,
+                describe_fees(&original)
+            );
+            original
+        }
+        None if args.action == ReplaceAction::SpeedUp => bail!(
+            "no tx of {:?} at nonce {} in the node's txpool to speed up, cancel it instead",
+            from,
+            nonce
+        ),
+        // Outbid whatever holds the nonce without knowing its fees.
+        None => replace::self_transfer(from, nonce),
+    };
+
+    // The fees a new tx would get, in case the network moved past the bump.
+    let fee_config = FeeConfig {
+        strategy: FeeStrategy::Eip1559 {
+            max_fee: None,
+            priority_fee: None,
+        },
+        base_fee_multiplier: 2.0,
+        refresh_interval: Duration::from_secs(5),
+        gas: GasPolicy::Fixed(TRANSFER_GAS),
+    };
+    let current = FeeOracle::new(provider.clone(), chain, fee_config)
+        .await?
+        .current();
+    let tx = replace::replacement(
+        from,
+        nonce,
+        &original,
+        args.action,
+        current,
+        args.fee_bump_percent,
+    );
+    let pending = signer
+        .inner()
+        .send_transaction(tx.clone(), None)
+        .await
+        .map_err(|err| anyhow!("{} tx failed, {}", args.action, SubmitError::classify(&err)))?;
+    let hash = pending.tx_hash();
+    tracing::info!(
+        target: COUNTER_CLIENT,
+        "{} of nonce {} of {:?} sent as {:?} with {}",
+        args.action,
+        nonce,
+        from,
+        hash,
+        describe_fees(&tx)
+    );
+
+    let receipt = match tokio::time::timeout(args.timeout, pending).await {
+        Ok(receipt) => receipt?,
+        Err(_) => bail!("{:?} not mined after {:?}", hash, args.timeout),
+    };
+    let Some(receipt) = receipt else {
+        bail!(
+            "{:?} was dropped, the nonce may have been taken by another tx",
+            hash
+        );
+    };
+    println!(
+        "{} of nonce {} of {:?} mined in tx {:?} block {:?}",
+        args.action, nonce, from, receipt.transaction_hash, receipt.block_number
+    );
+    Ok(())
+}
+
+fn describe_fees(tx: &TypedTransaction) -> String {
+    Fees::of(tx).map_or_else(|| "no fees".to_string(), |fees| fees.to_string())
+}
+
+/// The operator signer, or the pool account sending from `--address`.
+fn signer(
+    provider: &Provider<Transport>,
+    chain_id: u64,
+    operator: Arc<SignerProvider>,
+    args: &ReplaceArgs,
+) -> anyhow::Result<Arc<SignerProvider>> {
+    let Some(address) = args
+        .address
+        .filter(|address| *address != operator.inner().address())
+    else {
+        return Ok(operator);
+    };
+    let Some(mnemonic) = &args.pool.mnemonic else {
+        bail!(
+            "{:?} is not the operator account, pass --mnemonic to look it up in the wallet pool",
+            address
+        );
+    };
+    let pool = WalletPool::derive(
+        provider,
+        chain_id,
+        mnemonic.expose(),
+        &args.pool.derivation_path,
+        args.pool.pool_start_index,
+        args.pool.pool_size,
+    )?;
+    pool.accounts()
+        .iter()
+        .find(|account| account.inner().address() == address)
+        .cloned()
+        .ok_or_else(|| {
+            anyhow!(
+                "{:?} is neither the operator account nor one of the {} pool accounts",
+                address,
+                pool.len()
+            )
+        })
+}
@@ -0,0 +1,12 @@
+use anyhow::Context as _;
+use ethers_counter::{export, stats::RunStats};
+
+use crate::cli::ReportArgs;
+
+pub fn run(args: ReportArgs) -> anyhow::Result<()> {
+    let records = export::read_records(&args.file)?;
+    let stats = RunStats::from_records(&records)
+        .with_context(|| format!("reading {}", args.file.display()))?;
+    println!("{}", stats);
+    Ok(())
+}
@@ -0,0 +1,101 @@
+use ethers::utils::format_ether;
+
+use ethers_counter::{
+    export::RecordAppender,
+    watch::{self, Checkpoint, WatchConfig},
+    CounterClient, COUNTER_CLIENT,
+};
+
+use crate::{
+    cli::{ChainArgs, WatchArgs},
+    commands::deploy::Deployment,
+};
+
+pub async fn run(
+    client: &CounterClient,
+    chain_args: &ChainArgs,
+    args: WatchArgs,
+) -> anyhow::Result<()> {
+    let address = client.contract.address();
+    let checkpoint_path = (!args.no_checkpoint).then(|| {
+        args.checkpoint
+            .clone()
+            .unwrap_or_else(|| Checkpoint::path(&chain_args.deployments_dir, client.chain_id))
+    });
+    let checkpoint = match &checkpoint_path {
+        Some(path) => Checkpoint::load(path)?.filter(|checkpoint| {
+            let matches = checkpoint.chain_id == client.chain_id && checkpoint.address == address;
+            if !matches {
+                tracing::warn!(
+                    target: COUNTER_CLIENT,
+                    "ignoring checkpoint {} of {:?} on chain {}",
+                    path.display(),
+                    checkpoint.address,
+                    checkpoint.chain_id
+                );
+            }
+            matches
+        }),
+        None => None,
+    };
+    let mut output = args
+        .output
+        .as_deref()
+        .map(RecordAppender::open)
+        .transpose()?;
+
+    let from_block = match (args.from_block, &checkpoint) {
+        (Some(from_block), _) => from_block,
+        (None, Some(checkpoint)) => checkpoint.block_number + 1,
+        // Nothing to resume from: the whole history of a contract this
+        // tool deployed, otherwise only what comes next.
+        (None, None) => match Deployment::load(&chain_args.deployments_dir, client.chain_id)?
+            .filter(|deployment| deployment.address == address)
+            .and_then(|deployment| deployment.block_number)
+        {
+            Some(deployed_at) => deployed_at,
+            None => watch::safe_head(&client.provider, args.confirmations).await? + 1,
+        },
+    };
+    tracing::info!(
+        target: COUNTER_CLIENT,
+        "watching Withdrawal of {:?} from block {}, {} confirmations",
+        address,
+        from_block,
+        args.confirmations
+    );
+
+    let config = WatchConfig {
+        to_block: args.to_block,
+        chunk_size: args.chunk_size,
+        confirmations: args.confirmations,
+        poll_interval: args.poll_interval,
+    };
+    let mut watcher = client.watch(from_block, checkpoint, config);
+    while let Some(chunk) = watcher.next_chunk().await? {
+        for (event, record) in &chunk.withdrawals {
+            println!(
+                "withdrawal amount: {} ether when: {} block: {} tx: {:?}",
+                format_ether(event.amount),
+                event.when,
+                record.block_number,
+                record.tx_hash
+            );
+        }
+        if let Some(output) = &mut output {
+            let records: Vec<_> = chunk.withdrawals.iter().map(|(_, record)| record).collect();
+            output.append(&records)?;
+        }
+        if let Some(path) = &checkpoint_path {
+            chunk.checkpoint.save(path)?;
+        }
+        tracing::debug!(
+            target: COUNTER_CLIENT,
+            "processed blocks {}..={}, {} withdrawals",
+            chunk.from,
+            chunk.to,
+            chunk.withdrawals.len()
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,61 @@
+use anyhow::bail;
+use ethers::{providers::Middleware, utils::format_ether};
+use tokio::time::Instant;
+
+use ethers_counter::{CounterClient, COUNTER_CLIENT};
+
+use crate::cli::WithdrawArgs;
+
+pub async fn run(client: &CounterClient, args: WithdrawArgs) -> anyhow::Result<()> {
+    let contract = &client.contract;
+    let unlock_time = contract.unlock_time().call().await?;
+    let owner = contract.owner().call().await?;
+    let balance = client.provider.get_balance(contract.address(), None).await?;
+    tracing::info!(
+        target: COUNTER_CLIENT,
+        "lock {:?} owner {:?} unlock time {} balance {} ether",
+        contract.address(),
+        owner,
+        unlock_time,
+        format_ether(balance)
+    );
+    // Before waiting, so a wrong key fails right away.
+    client.ensure_owner().await?;
+    if args.wait {
+        wait_for_unlock(client, &args).await?;
+    }
+
+    let (withdrawal, receipt) = client.withdraw().await?;
+    println!(
+        "withdrew {} ether at {} in tx {:?} block {:?}",
+        format_ether(withdrawal.amount),
+        withdrawal.when,
+        receipt.transaction_hash,
+        receipt.block_number
+    );
+    Ok(())
+}
+
+/// Returns once the latest block is at or past the unlock time, the contract
+/// checks `block.timestamp` rather than the local clock.
+async fn wait_for_unlock(client: &CounterClient, args: &WithdrawArgs) -> anyhow::Result<()> {
+    let started = Instant::now();
+    while let Some(remaining) = client.locked_for().await? {
+        if args
+            .max_wait
+            .is_some_and(|max_wait| started.elapsed() >= max_wait)
+        {
+            bail!(
+                "still locked for another {}s after waiting {:?}",
+                remaining,
+                started.elapsed()
+            );
+        }
+        tracing::info!(target: COUNTER_CLIENT, "locked for another {}s, waiting", remaining);
+        let sleep = args
+            .poll_interval
+            .min(std::time::Duration::from_secs(remaining.max(1)));
+        tokio::time::sleep(sleep).await;
+    }
+    Ok(())
+}
@@ -0,0 +1,547 @@
+use std::{
+    fmt,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering},
+        Arc, Weak,
+    },
+    time::Duration,
+};
+
+use anyhow::bail;
+use ethers::{
+    providers::{JsonRpcClient, PubsubClient, RpcError},
+    types::{Bytes, U256, U64},
+    utils::keccak256,
+};
+use rand::Rng;
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+use tokio::time::Instant;
+
+use crate::{
+    config::redact_url,
+    transport::{Transport, TransportError},
+    COUNTER_CLIENT,
+};
+
+/// Consecutive transient failures before an endpoint is taken out of rotation.
+const MAX_CONSECUTIVE_FAILURES: u32 = 3;
+/// Weight of the newest sample in the latency average.
+const LATENCY_EWMA_ALPHA: f64 = 0.2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Routing {
+    RoundRobin,
+    /// Random, weighted by the inverse of the recent latency.
+    Latency,
+}
+
+impl FromStr for Routing {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "round-robin" => Ok(Routing::RoundRobin),
+            "latency" => Ok(Routing::Latency),
+            _ => bail!("unknown routing `{}`, expected `round-robin` or `latency`", s),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct EndpointConfig {
+    pub routing: Routing,
+    /// Retries of a request after a transient error, each on another endpoint
+    /// when one is available.
+    pub retries: u32,
+    /// First retry delay, doubled on every further retry.
+    pub backoff: Duration,
+    pub health_interval: Duration,
+}
+
+impl Default for EndpointConfig {
+    fn default() -> Self {
+        Self {
+            routing: Routing::RoundRobin,
+            retries: 3,
+            backoff: Duration::from_millis(200),
+            health_interval: Duration::from_secs(5),
+        }
+    }
+}
+
+/// One RPC node and what was observed talking to it.
+#[derive(Debug)]
+pub struct Endpoint {
+    pub url: String,
+    transport: Transport,
+    healthy: AtomicBool,
+    consecutive_failures: AtomicU32,
+    requests: AtomicU64,
+    errors: AtomicU64,
+    latency_total_us: AtomicU64,
+    latency_ewma_us: AtomicU64,
+    head: AtomicU64,
+}
+
+impl Endpoint {
+    fn new(url: String, transport: Transport) -> Self {
+        Self {
+            url,
+            transport,
+            healthy: AtomicBool::new(true),
+            consecutive_failures: AtomicU32::new(0),
+            requests: AtomicU64::new(0),
+            errors: AtomicU64::new(0),
+            latency_total_us: AtomicU64::new(0),
+            latency_ewma_us: AtomicU64::new(0),
+            head: AtomicU64::new(0),
+        }
+    }
+
+    pub fn transport(&self) -> &Transport {
+        &self.transport
+    }
+
+    pub fn is_healthy(&self) -> bool {
+        self.healthy.load(Ordering::Relaxed)
+    }
+
+    fn record_success(&self, latency: Duration) {
+        let us = latency.as_micros() as u64;
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        self.latency_total_us.fetch_add(us, Ordering::Relaxed);
+        let ewma = self.latency_ewma_us.load(Ordering::Relaxed);
+        let ewma = if ewma == 0 {
+            us
+        } else {
+            (LATENCY_EWMA_ALPHA * us as f64 + (1.0 - LATENCY_EWMA_ALPHA) * ewma as f64) as u64
+        };
+        self.latency_ewma_us.store(ewma, Ordering::Relaxed);
+        self.consecutive_failures.store(0, Ordering::Relaxed);
+        if !self.healthy.swap(true, Ordering::Relaxed) {
+            tracing::info!(target: COUNTER_CLIENT, "endpoint {} is back in rotation", redact_url(&self.url));
+        }
+    }
+
+    fn record_failure(&self, err: &TransportError) {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        self.errors.fetch_add(1, Ordering::Relaxed);
+        let failures = self.consecutive_failures.fetch_add(1, Ordering::Relaxed) + 1;
+        if failures >= MAX_CONSECUTIVE_FAILURES && self.healthy.swap(false, Ordering::Relaxed) {
+            tracing::warn!(
+                target: COUNTER_CLIENT,
+                "endpoint {} out of rotation after {} failures, last err: {}",
+                redact_url(&self.url),
+                failures,
+                err
+            );
+        }
+    }
+
+    /// Recent latency, used as the routing weight.
+    fn latency(&self) -> Duration {
+        Duration::from_micros(self.latency_ewma_us.load(Ordering::Relaxed))
+    }
+}
+
+/// Spreads requests over several RPC endpoints of the same chain, retrying
+/// transient errors with backoff and failing over to healthy endpoints.
+#[derive(Debug)]
+pub struct EndpointPool {
+    endpoints: Vec<Endpoint>,
+    config: EndpointConfig,
+    next: AtomicUsize,
+    /// Subscriptions are per node, so they all go to one endpoint.
+    pubsub: Option<usize>,
+}
+
+impl EndpointPool {
+    /// Connects every endpoint; the ones that fail are skipped as long as
+    /// one connects.
+    pub async fn connect(
+        urls: &[String],
+        config: EndpointConfig,
+    ) -> Result<Arc<Self>, TransportError> {
+        let mut endpoints = vec![];
+        let mut last_err = None;
+        for url in urls {
+            match Transport::connect(url).await {
+                Ok(transport) => endpoints.push(Endpoint::new(url.clone(), transport)),
+                Err(err) => {
+                    tracing::warn!(
+                        target: COUNTER_CLIENT,
+                        "endpoint {} skipped, connect failed with err: {}",
+                        redact_url(url),
+                        err
+                    );
+                    last_err = Some(err);
+                }
+            }
+        }
+        if endpoints.is_empty() {
+            return Err(last_err.unwrap_or(TransportError::NoEndpoint));
+        }
+        let pubsub = endpoints.iter().position(|e| e.transport.is_pubsub());
+        let pool = Arc::new(Self {
+            endpoints,
+            config,
+            next: AtomicUsize::new(0),
+            pubsub,
+        });
+        tokio::spawn(health_check(Arc::downgrade(&pool)));
+        Ok(pool)
+    }
+
+    pub fn endpoints(&self) -> &[Endpoint] {
+        &self.endpoints
+    }
+
+    pub fn is_pubsub(&self) -> bool {
+        self.pubsub.is_some()
+    }
+
+    /// Picks an endpoint outside `tried`, preferring healthy ones; `None`
+    /// once every endpoint was tried.
+    fn pick(&self, tried: &[usize]) -> Option<usize> {
+        let untried = |i: &usize| !tried.contains(i);
+        let mut candidates: Vec<usize> = (0..self.endpoints.len())
+            .filter(untried)
+            .filter(|&i| self.endpoints[i].is_healthy())
+            .collect();
+        if candidates.is_empty() {
+            candidates = (0..self.endpoints.len()).filter(untried).collect();
+        }
+        if candidates.len() <= 1 {
+            return candidates.first().copied();
+        }
+        let picked = match self.config.routing {
+            Routing::RoundRobin => {
+                candidates[self.next.fetch_add(1, Ordering::Relaxed) % candidates.len()]
+            }
+            Routing::Latency => {
+                // Endpoints without a sample yet get the weight of a 1ms one.
+                let weights: Vec<f64> = candidates
+                    .iter()
+                    .map(|&i| 1.0 / self.endpoints[i].latency().as_secs_f64().max(0.001))
+                    .collect();
+                let mut target = rand::thread_rng().gen_range(0.0..weights.iter().sum::<f64>());
+                let mut picked = candidates[candidates.len() - 1];
+                for (&i, weight) in candidates.iter().zip(&weights) {
+                    if target < *weight {
+                        picked = i;
+                        break;
+                    }
+                    target -= weight;
+                }
+                picked
+            }
+        };
+        Some(picked)
+    }
+
+    async fn request_on<R>(&self, idx: usize, method: &str, params: Value) -> Result<R, TransportError>
+    where
+        R: DeserializeOwned + Send,
+    {
+        let endpoint = &self.endpoints[idx];
+        let started = Instant::now();
+        let result = endpoint.transport.request(method, params).await;
+        match &result {
+            Ok(_) => endpoint.record_success(started.elapsed()),
+            Err(err) if is_transient(err) => endpoint.record_failure(err),
+            // The node answered, it's alive.
+            Err(_) => endpoint.record_success(started.elapsed()),
+        }
+        result
+    }
+}
+
+#[async_trait::async_trait]
+impl JsonRpcClient for EndpointPool {
+    type Error = TransportError;
+
+    async fn request<T, R>(&self, method: &str, params: T) -> Result<R, Self::Error>
+    where
+        T: fmt::Debug + Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+    {
+        // Serialized once, every transport below then sees the same type.
+        // `()` would become `null`, which not every node accepts.
+        let params = match serde_json::to_value(params)? {
+            Value::Null => Value::Array(vec![]),
+            params => params,
+        };
+        if method == "eth_subscribe" || method == "eth_unsubscribe" {
+            let idx = self.pubsub.ok_or(TransportError::NoPubsub("http"))?;
+            return self.request_on(idx, method, params).await;
+        }
+
+        let mut tried = vec![];
+        let mut attempt = 0;
+        loop {
+            let idx = match self.pick(&tried) {
+                Some(idx) => idx,
+                None => {
+                    // Every endpoint failed once, go round again.
+                    tried.clear();
+                    self.pick(&tried).ok_or(TransportError::NoEndpoint)?
+                }
+            };
+            tried.push(idx);
+            let err = match self.request_on(idx, method, params.clone()).await {
+                Ok(res) => return Ok(res),
+                Err(err) => err,
+            };
+            // A resent raw tx the first attempt already delivered.
+            if attempt > 0 && method == "eth_sendRawTransaction" && is_already_known(&err) {
+                if let Some(hash) = raw_tx_hash(&params) {
+                    return Ok(serde_json::from_value(serde_json::json!(hash))?);
+                }
+            }
+            if !is_transient(&err) || attempt >= self.config.retries {
+                return Err(err);
+            }
+            let backoff = self.config.backoff * 2u32.saturating_pow(attempt);
+            tracing::warn!(
+                target: COUNTER_CLIENT,
+                "{} on {} failed with err: {}, retrying in {:?}",
+                method,
+                redact_url(&self.endpoints[idx].url),
+                err,
+                backoff
+            );
+            tokio::time::sleep(backoff).await;
+            attempt += 1;
+        }
+    }
+}
+
+impl PubsubClient for EndpointPool {
+    type NotificationStream = <Transport as PubsubClient>::NotificationStream;
+
+    fn subscribe<T: Into<U256>>(&self, id: T) -> Result<Self::NotificationStream, Self::Error> {
+        let idx = self.pubsub.ok_or(TransportError::NoPubsub("http"))?;
+        self.endpoints[idx].transport.subscribe(id)
+    }
+
+    fn unsubscribe<T: Into<U256>>(&self, id: T) -> Result<(), Self::Error> {
+        let idx = self.pubsub.ok_or(TransportError::NoPubsub("http"))?;
+        self.endpoints[idx].transport.unsubscribe(id)
+    }
+}
+
+/// Errors where the node never answered, as opposed to JSON-RPC error
+/// responses and undecodable results.
+fn is_transient(err: &TransportError) -> bool {
+    !matches!(
+        err,
+        TransportError::NoPubsub(_) | TransportError::Url(_) | TransportError::NoEndpoint
+    )
+        && !err.is_error_response()
+        && !err.is_serde_error()
+}
+
+fn is_already_known(err: &TransportError) -> bool {
+    err.as_error_response().is_some_and(|err| {
+        let message = err.message.to_ascii_lowercase();
+        message.contains("already known") || message.contains("already imported")
+    })
+}
+
+fn raw_tx_hash(params: &Value) -> Option<String> {
+    let (raw,): (Bytes,) = serde_json::from_value(params.clone()).ok()?;
+    Some(format!("0x{}", hex::encode(keccak256(raw))))
+}
+
+/// Polls the head of every endpoint while the pool is alive; brings failed
+/// endpoints back once they answer again.
+async fn health_check(pool: Weak<EndpointPool>) {
+    loop {
+        let Some(interval) = pool.upgrade().map(|pool| pool.config.health_interval) else {
+            return;
+        };
+        tokio::time::sleep(interval).await;
+        let Some(pool) = pool.upgrade() else { return };
+        for (idx, endpoint) in pool.endpoints.iter().enumerate() {
+            match pool.request_on::<U64>(idx, "eth_blockNumber", Value::Array(vec![])).await {
+                Ok(head) => endpoint.head.store(head.as_u64(), Ordering::Relaxed),
+                Err(err) => tracing::debug!(
+                    target: COUNTER_CLIENT,
+                    "health check of {} failed with err: {}",
+                    redact_url(&endpoint.url),
+                    err
+                ),
+            }
+        }
+    }
+}
+
+/// Per-endpoint comparison of a run.
+pub struct EndpointReport<'a>(pub &'a EndpointPool);
+
+impl fmt::Display for EndpointReport<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "==== endpoints ====")?;
+        for endpoint in self.0.endpoints() {
+            let requests = endpoint.requests.load(Ordering::Relaxed);
+            let errors = endpoint.errors.load(Ordering::Relaxed);
+            let ok = requests - errors;
+            let mean = match ok {
+                0 => Duration::ZERO,
+                ok => Duration::from_micros(endpoint.latency_total_us.load(Ordering::Relaxed) / ok),
+            };
+            writeln!(
+                f,
+                "{} {} requests={} errors={} mean={:?} recent={:?} head={}",
+                redact_url(&endpoint.url),
+                if endpoint.is_healthy() { "up" } else { "down" },
+                requests,
+                errors,
+                mean,
+                endpoint.latency(),
+                endpoint.head.load(Ordering::Relaxed),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::mock_rpc;
+
+    /// A node answering `eth_chainId` after `delay`, with the number of those
+    /// calls. Health checks are answered too but not counted.
+    async fn node(addr: &str, delay: Duration) -> Arc<AtomicUsize> {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counted = calls.clone();
+        mock_rpc::spawn_on(addr, move |method, _| {
+            if method == "eth_chainId" {
+                counted.fetch_add(1, Ordering::Relaxed);
+                std::thread::sleep(delay);
+            }
+            Ok(json!("0x539"))
+        })
+        .await;
+        calls
+    }
+
+    async fn pool(
+        addrs: &[&str],
+        routing: Routing,
+        health_interval: Duration,
+    ) -> Arc<EndpointPool> {
+        let urls: Vec<String> = addrs
+            .iter()
+            .map(|addr| format!("http://{}", addr))
+            .collect();
+        let config = EndpointConfig {
+            routing,
+            retries: 3,
+            backoff: Duration::from_millis(20),
+            health_interval,
+        };
+        EndpointPool::connect(&urls, config).await.unwrap()
+    }
+
+    async fn chain_id(pool: &EndpointPool) -> Result<U64, TransportError> {
+        pool.request("eth_chainId", ()).await
+    }
+
+    const NO_HEALTH_CHECK: Duration = Duration::from_secs(3600);
+
+    #[tokio::test]
+    async fn round_robin_spreads_requests_evenly() {
+        let (a, b) = (mock_rpc::free_addr().await, mock_rpc::free_addr().await);
+        let (calls_a, calls_b) = (
+            node(&a, Duration::ZERO).await,
+            node(&b, Duration::ZERO).await,
+        );
+        let pool = pool(&[&a, &b], Routing::RoundRobin, NO_HEALTH_CHECK).await;
+
+        for _ in 0..10 {
+            assert_eq!(chain_id(&pool).await.unwrap(), U64::from(1337));
+        }
+        assert_eq!(calls_a.load(Ordering::Relaxed), 5);
+        assert_eq!(calls_b.load(Ordering::Relaxed), 5);
+    }
+
+    #[tokio::test]
+    async fn failed_requests_are_retried_on_another_endpoint_after_backoff() {
+        let (down, up) = (mock_rpc::free_addr().await, mock_rpc::free_addr().await);
+        let calls = node(&up, Duration::ZERO).await;
+        let pool = pool(&[&down, &up], Routing::RoundRobin, NO_HEALTH_CHECK).await;
+
+        // Round robin starts with the endpoint that is down.
+        let started = Instant::now();
+        assert_eq!(chain_id(&pool).await.unwrap(), U64::from(1337));
+        assert!(started.elapsed() >= pool.config.backoff);
+        assert_eq!(pool.endpoints()[0].errors.load(Ordering::Relaxed), 1);
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn failing_endpoints_leave_rotation_until_a_health_check_passes() {
+        let (down, up) = (mock_rpc::free_addr().await, mock_rpc::free_addr().await);
+        node(&up, Duration::ZERO).await;
+        let pool = pool(
+            &[&down, &up],
+            Routing::RoundRobin,
+            Duration::from_millis(50),
+        )
+        .await;
+
+        let mut requests = 0;
+        while pool.endpoints()[0].is_healthy() {
+            chain_id(&pool).await.unwrap();
+            requests += 1;
+            assert!(requests <= 2 * MAX_CONSECUTIVE_FAILURES, "never ejected");
+        }
+        assert!(
+            pool.endpoints()[0].errors.load(Ordering::Relaxed) >= MAX_CONSECUTIVE_FAILURES as u64
+        );
+        for _ in 0..10 {
+            assert_eq!(pool.pick(&[]), Some(1));
+        }
+
+        node(&down, Duration::ZERO).await;
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while !pool.endpoints()[0].is_healthy() {
+            assert!(Instant::now() < deadline, "never re-admitted");
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        let picked: Vec<_> = (0..4).filter_map(|_| pool.pick(&[])).collect();
+        assert!(picked.contains(&0));
+    }
+
+    #[tokio::test]
+    async fn latency_routing_prefers_the_faster_endpoint() {
+        let (slow, fast) = (mock_rpc::free_addr().await, mock_rpc::free_addr().await);
+        let slow_calls = node(&slow, Duration::from_millis(30)).await;
+        let fast_calls = node(&fast, Duration::ZERO).await;
+        let pool = pool(&[&slow, &fast], Routing::Latency, NO_HEALTH_CHECK).await;
+        for idx in [0, 1] {
+            pool.request_on::<U64>(idx, "eth_chainId", json!([]))
+                .await
+                .unwrap();
+        }
+        assert!(pool.endpoints()[0].latency() > pool.endpoints()[1].latency());
+
+        for _ in 0..40 {
+            chain_id(&pool).await.unwrap();
+        }
+        let slow_calls = slow_calls.load(Ordering::Relaxed) - 1;
+        let fast_calls = fast_calls.load(Ordering::Relaxed) - 1;
+        assert_eq!(slow_calls + fast_calls, 40);
+        assert!(
+            slow_calls <= 10,
+            "{} of 40 on the slow endpoint",
+            slow_calls
+        );
+    }
+}
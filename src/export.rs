@@ -0,0 +1,317 @@
+use std::{
+    fs::{self, File},
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::bail;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tokio::time::Instant;
+
+use crate::{
+    stats::RunStats,
+    submit_error::SubmitErrorKind,
+    tracker::{SentTx, TxStatus},
+    COUNTER_CLIENT,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Json,
+    Csv,
+    All,
+}
+
+impl FromStr for OutputFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "json" | "jsonl" => Ok(OutputFormat::Json),
+            "csv" => Ok(OutputFormat::Csv),
+            "all" => Ok(OutputFormat::All),
+            _ => bail!("unknown output format `{}`, expected `json`, `csv` or `all`", s),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ExportConfig {
+    pub dir: PathBuf,
+    pub format: OutputFormat,
+}
+
+/// Flat, serializable view of a [`SentTx`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TxRecord {
+    pub idx: u64,
+    pub from: String,
+    pub hash: Option<String>,
+    pub nonce: Option<u64>,
+    pub gas_used: Option<u64>,
+    pub effective_gas_price: Option<u128>,
+    pub block_number: Option<u64>,
+    pub submitted_at_ms: u64,
+    pub submit_latency_ms: f64,
+    pub included_at_ms: Option<u64>,
+    /// Only recorded with a pending-transaction subscription.
+    #[serde(default)]
+    pub pending_at_ms: Option<u64>,
+    pub status: TxStatus,
+    pub error: Option<String>,
+    #[serde(default)]
+    pub error_kind: Option<SubmitErrorKind>,
+    /// Every rejection on the way, comma separated.
+    #[serde(default)]
+    pub submit_errors: Option<String>,
+}
+
+impl TxRecord {
+    fn new(tx: &SentTx, clock: &WallClock) -> Self {
+        let receipt = tx.receipt.as_ref();
+        Self {
+            idx: tx.idx,
+            from: format!("{:?}", tx.from),
+            hash: tx.hash.map(|hash| format!("{:?}", hash)),
+            nonce: tx.nonce.map(|nonce| nonce.as_u64()),
+            gas_used: receipt.and_then(|r| r.gas_used).map(|gas| gas.as_u64()),
+            effective_gas_price: receipt
+                .and_then(|r| r.effective_gas_price)
+                .map(|price| price.as_u128()),
+            block_number: receipt.and_then(|r| r.block_number).map(|n| n.as_u64()),
+            submitted_at_ms: clock.unix_millis(tx.sent_at),
+            submit_latency_ms: tx.submit_latency.as_secs_f64() * 1000.0,
+            included_at_ms: tx.included_at.map(|at| clock.unix_millis(at)),
+            pending_at_ms: tx.pending_at.map(|at| clock.unix_millis(at)),
+            status: tx.status,
+            error: tx.error.clone(),
+            error_kind: tx.error_kind,
+            submit_errors: (!tx.submit_errors.is_empty()).then(|| {
+                let kinds: Vec<_> = tx.submit_errors.iter().map(|kind| kind.to_string()).collect();
+                kinds.join(",")
+            }),
+        }
+    }
+
+    pub fn submit_errors(&self) -> Vec<SubmitErrorKind> {
+        self.submit_errors
+            .iter()
+            .flat_map(|kinds| kinds.split(','))
+            .filter_map(|kind| kind.parse().ok())
+            .collect()
+    }
+}
+
+/// Serializable view of [`RunStats`], latencies in milliseconds.
+#[derive(Debug, Clone, Serialize)]
+pub struct RunSummary {
+    pub sent: usize,
+    pub mined: usize,
+    pub reverted: usize,
+    pub replaced: usize,
+    pub dropped: usize,
+    pub timed_out: usize,
+    pub failed: usize,
+    pub send_tps: f64,
+    pub effective_tps: f64,
+    pub submit_p50_ms: Option<f64>,
+    pub submit_p90_ms: Option<f64>,
+    pub submit_p99_ms: Option<f64>,
+    pub submit_max_ms: Option<f64>,
+    pub inclusion_p50_ms: Option<f64>,
+    pub inclusion_p90_ms: Option<f64>,
+    pub inclusion_p99_ms: Option<f64>,
+    pub inclusion_max_ms: Option<f64>,
+    pub blocks: usize,
+}
+
+impl From<&RunStats> for RunSummary {
+    fn from(stats: &RunStats) -> Self {
+        use TxStatus::*;
+
+        let ms = |d: Option<Duration>| d.map(|d| d.as_secs_f64() * 1000.0);
+        Self {
+            sent: stats.sent,
+            mined: stats.count(Mined),
+            reverted: stats.count(Reverted),
+            replaced: stats.count(Replaced),
+            dropped: stats.count(Dropped),
+            timed_out: stats.count(TimedOut),
+            failed: stats.count(Failed),
+            send_tps: stats.send_tps(),
+            effective_tps: stats.effective_tps(),
+            submit_p50_ms: ms(stats.submit_latency.percentile(50.0)),
+            submit_p90_ms: ms(stats.submit_latency.percentile(90.0)),
+            submit_p99_ms: ms(stats.submit_latency.percentile(99.0)),
+            submit_max_ms: ms(stats.submit_latency.max()),
+            inclusion_p50_ms: ms(stats.inclusion_latency.percentile(50.0)),
+            inclusion_p90_ms: ms(stats.inclusion_latency.percentile(90.0)),
+            inclusion_p99_ms: ms(stats.inclusion_latency.percentile(99.0)),
+            inclusion_max_ms: ms(stats.inclusion_latency.max()),
+            blocks: stats.txs_per_block.len(),
+        }
+    }
+}
+
+/// Maps monotonic [`Instant`]s onto wall-clock time.
+struct WallClock {
+    instant: Instant,
+    system: SystemTime,
+}
+
+impl WallClock {
+    fn now() -> Self {
+        Self {
+            instant: Instant::now(),
+            system: SystemTime::now(),
+        }
+    }
+
+    fn unix_millis(&self, at: Instant) -> u64 {
+        let wall = self.system - self.instant.saturating_duration_since(at);
+        wall.duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64
+    }
+}
+
+/// Writes `<run>.txs.{jsonl,csv}` and `<run>.summary.{json,csv}` into the
+/// configured directory, returning the paths written.
+pub fn export_run(
+    config: &ExportConfig,
+    run_id: &str,
+    txs: &[SentTx],
+    stats: &RunStats,
+) -> anyhow::Result<Vec<PathBuf>> {
+    fs::create_dir_all(&config.dir)?;
+    let clock = WallClock::now();
+    let records: Vec<_> = txs.iter().map(|tx| TxRecord::new(tx, &clock)).collect();
+    let summary = RunSummary::from(stats);
+
+    let mut written = vec![];
+    if matches!(config.format, OutputFormat::Json | OutputFormat::All) {
+        let path = config.dir.join(format!("{}.txs.jsonl", run_id));
+        write_jsonl(&path, &records)?;
+        written.push(path);
+
+        let path = config.dir.join(format!("{}.summary.json", run_id));
+        fs::write(&path, serde_json::to_string_pretty(&summary)?)?;
+        written.push(path);
+    }
+    if matches!(config.format, OutputFormat::Csv | OutputFormat::All) {
+        let path = config.dir.join(format!("{}.txs.csv", run_id));
+        write_csv(&path, &records)?;
+        written.push(path);
+
+        let path = config.dir.join(format!("{}.summary.csv", run_id));
+        write_csv(&path, &[summary])?;
+        written.push(path);
+    }
+
+    for path in &written {
+        tracing::info!(target: COUNTER_CLIENT, "wrote {}", path.display());
+    }
+    Ok(written)
+}
+
+fn write_jsonl<T: Serialize>(path: &Path, rows: &[T]) -> anyhow::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    for row in rows {
+        serde_json::to_writer(&mut writer, row)?;
+        writer.write_all(b"\n")?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn write_csv<T: Serialize>(path: &Path, rows: &[T]) -> anyhow::Result<()> {
+    let mut writer = csv::Writer::from_path(path)?;
+    for row in rows {
+        writer.serialize(row)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Reads tx records back from a `.jsonl` or `.csv` export.
+pub fn read_records(path: &Path) -> anyhow::Result<Vec<TxRecord>> {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("csv") => read_csv(path),
+        Some("jsonl" | "json") => read_jsonl(path),
+        _ => bail!("cannot tell the format of {}, expected .jsonl or .csv", path.display()),
+    }
+}
+
+fn read_jsonl<T: DeserializeOwned>(path: &Path) -> anyhow::Result<Vec<T>> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut rows = vec![];
+    for line in reader.lines() {
+        let line = line?;
+        if !line.trim().is_empty() {
+            rows.push(serde_json::from_str(&line)?);
+        }
+    }
+    Ok(rows)
+}
+
+fn read_csv<T: DeserializeOwned>(path: &Path) -> anyhow::Result<Vec<T>> {
+    let mut reader = csv::Reader::from_path(path)?;
+    let rows = reader.deserialize().collect::<Result<_, _>>()?;
+    Ok(rows)
+}
+
+/// Run identifier used in export file names.
+pub fn run_id() -> String {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    format!("run-{}", secs)
+}
+
+/// Appends records to a `.jsonl` or `.csv` file across restarts, for
+/// long-running commands that export as they go.
+pub enum RecordAppender {
+    Jsonl(BufWriter<File>),
+    Csv(Box<csv::Writer<File>>),
+}
+
+impl RecordAppender {
+    /// The format follows the extension, JSON lines unless it is `.csv`.
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+        let file = fs::OpenOptions::new().create(true).append(true).open(path)?;
+        if path.extension().is_some_and(|ext| ext == "csv") {
+            // The header was written when the file was created.
+            let fresh = file.metadata()?.len() == 0;
+            let writer = csv::WriterBuilder::new().has_headers(fresh).from_writer(file);
+            Ok(RecordAppender::Csv(Box::new(writer)))
+        } else {
+            Ok(RecordAppender::Jsonl(BufWriter::new(file)))
+        }
+    }
+
+    /// Writes and flushes `rows`, so a crash loses nothing already appended.
+    pub fn append<T: Serialize>(&mut self, rows: &[T]) -> anyhow::Result<()> {
+        match self {
+            RecordAppender::Jsonl(writer) => {
+                for row in rows {
+                    serde_json::to_writer(&mut *writer, row)?;
+                    writer.write_all(b"\n")?;
+                }
+                writer.flush()?;
+            }
+            RecordAppender::Csv(writer) => {
+                for row in rows {
+                    writer.serialize(row)?;
+                }
+                writer.flush()?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,378 @@
+use std::{
+    fmt,
+    str::FromStr,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use anyhow::bail;
+use ethers::{
+    providers::{Middleware, Provider},
+    types::{
+        transaction::eip2718::TypedTransaction, BlockNumber, Eip1559TransactionRequest,
+        TransactionRequest, U256,
+    },
+    utils::format_units,
+};
+use tokio::task::JoinHandle;
+
+use crate::{
+    chain::{self, ChainCapabilities, ChainError},
+    revert,
+    transport::Transport,
+    SignerProvider, COUNTER_CLIENT,
+};
+
+/// Fee fields applied to every transaction of a run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fees {
+    Legacy { gas_price: U256 },
+    Eip1559 { max_fee: U256, priority_fee: U256 },
+}
+
+impl Fees {
+    /// The fees already set on `tx`, `None` while unpriced.
+    pub fn of(tx: &TypedTransaction) -> Option<Self> {
+        match tx {
+            TypedTransaction::Eip1559(tx) => Some(Fees::Eip1559 {
+                max_fee: tx.max_fee_per_gas?,
+                priority_fee: tx.max_priority_fee_per_gas?,
+            }),
+            _ => Some(Fees::Legacy {
+                gas_price: tx.gas_price()?,
+            }),
+        }
+    }
+
+    /// Every fee raised by `percent`, rounding up so small fees still move.
+    pub fn bumped(&self, percent: u64) -> Self {
+        let bump = |fee: U256| fee + (fee * percent + 99) / 100;
+        match *self {
+            Fees::Legacy { gas_price } => Fees::Legacy {
+                gas_price: bump(gas_price),
+            },
+            Fees::Eip1559 {
+                max_fee,
+                priority_fee,
+            } => Fees::Eip1559 {
+                max_fee: bump(max_fee),
+                priority_fee: bump(priority_fee),
+            },
+        }
+    }
+
+    /// Every fee raised to at least that of `floor`, a `floor` of the other
+    /// type leaves the fees as they are.
+    pub fn at_least(&self, floor: Fees) -> Self {
+        match (*self, floor) {
+            (Fees::Legacy { gas_price }, Fees::Legacy { gas_price: floor }) => Fees::Legacy {
+                gas_price: gas_price.max(floor),
+            },
+            (
+                Fees::Eip1559 {
+                    max_fee,
+                    priority_fee,
+                },
+                Fees::Eip1559 {
+                    max_fee: floor_max_fee,
+                    priority_fee: floor_priority_fee,
+                },
+            ) => Fees::Eip1559 {
+                max_fee: max_fee.max(floor_max_fee),
+                priority_fee: priority_fee.max(floor_priority_fee),
+            },
+            (fees, _) => fees,
+        }
+    }
+
+    /// Sets the fee fields, converting the transaction to the matching type.
+    pub fn apply(&self, tx: &mut TypedTransaction) {
+        match *self {
+            Fees::Legacy { gas_price } => {
+                if !matches!(tx, TypedTransaction::Legacy(_)) {
+                    let legacy: TransactionRequest = tx.clone().into();
+                    *tx = TypedTransaction::Legacy(legacy);
+                }
+                tx.set_gas_price(gas_price);
+            }
+            Fees::Eip1559 {
+                max_fee,
+                priority_fee,
+            } => {
+                let mut eip1559: Eip1559TransactionRequest = tx.clone().into();
+                eip1559.max_fee_per_gas = Some(max_fee);
+                eip1559.max_priority_fee_per_gas = Some(priority_fee);
+                *tx = TypedTransaction::Eip1559(eip1559);
+            }
+        }
+    }
+}
+
+impl fmt::Display for Fees {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let gwei = |v: U256| format_units(v, "gwei").unwrap_or_else(|_| v.to_string());
+        match *self {
+            Fees::Legacy { gas_price } => write!(f, "legacy gas price {} gwei", gwei(gas_price)),
+            Fees::Eip1559 {
+                max_fee,
+                priority_fee,
+            } => write!(
+                f,
+                "eip1559 max fee {} gwei priority fee {} gwei",
+                gwei(max_fee),
+                gwei(priority_fee)
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FeeStrategyKind {
+    Legacy,
+    Eip1559,
+    FeeHistory,
+}
+
+impl FromStr for FeeStrategyKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "legacy" => Ok(FeeStrategyKind::Legacy),
+            "eip1559" => Ok(FeeStrategyKind::Eip1559),
+            "fee-history" => Ok(FeeStrategyKind::FeeHistory),
+            _ => bail!(
+                "unknown fee strategy `{}`, expected `legacy`, `eip1559` or `fee-history`",
+                s
+            ),
+        }
+    }
+}
+
+/// How the fees of a run are chosen. Pre-London chains get the node's
+/// `eth_gasPrice` unless a legacy gas price is fixed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FeeStrategy {
+    /// Legacy `gasPrice`, fixed or the base fee times the multiplier.
+    Legacy { gas_price: Option<U256> },
+    /// Type-2 fees, the max fee fixed or the base fee times the multiplier
+    /// plus the priority fee. Without a priority fee the node's
+    /// `eth_maxPriorityFeePerGas` is used where available, zero otherwise.
+    Eip1559 {
+        max_fee: Option<U256>,
+        priority_fee: Option<U256>,
+    },
+    /// Type-2 fees with the priority fee taken from `eth_feeHistory`.
+    FeeHistory { blocks: u64, percentile: f64 },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GasPolicy {
+    Fixed(u64),
+    /// `eth_estimateGas` per transaction, scaled by the margin.
+    Estimate { margin: f64 },
+}
+
+#[derive(Debug, Clone)]
+pub struct FeeConfig {
+    pub strategy: FeeStrategy,
+    pub base_fee_multiplier: f64,
+    pub refresh_interval: Duration,
+    pub gas: GasPolicy,
+}
+
+/// Keeps the fees of a run current as the base fee moves.
+pub struct FeeOracle {
+    provider: Provider<Transport>,
+    chain: ChainCapabilities,
+    config: FeeConfig,
+    current: RwLock<Fees>,
+}
+
+impl FeeOracle {
+    pub async fn new(
+        provider: Provider<Transport>,
+        chain: ChainCapabilities,
+        config: FeeConfig,
+    ) -> Result<Arc<Self>, ChainError> {
+        let legacy_only = matches!(config.strategy, FeeStrategy::Legacy { .. });
+        if !chain.london && !legacy_only {
+            tracing::warn!(
+                target: COUNTER_CLIENT,
+                "chain {} has no base fee, falling back to legacy eth_gasPrice",
+                chain.chain_id
+            );
+        }
+        let fees = compute(&provider, &chain, &config).await?;
+        tracing::info!(target: COUNTER_CLIENT, "fees: {}", fees);
+        Ok(Arc::new(Self {
+            provider,
+            chain,
+            config,
+            current: RwLock::new(fees),
+        }))
+    }
+
+    pub fn current(&self) -> Fees {
+        *self.current.read().unwrap()
+    }
+
+    pub async fn refresh(&self) -> Result<Fees, ChainError> {
+        let fees = compute(&self.provider, &self.chain, &self.config).await?;
+        let previous = std::mem::replace(&mut *self.current.write().unwrap(), fees);
+        if previous != fees {
+            tracing::info!(target: COUNTER_CLIENT, "fees updated: {}", fees);
+        }
+        Ok(fees)
+    }
+
+    /// Refreshes in the background until the handle is aborted.
+    pub fn spawn_refresh(self: &Arc<Self>) -> JoinHandle<()> {
+        let oracle = self.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(oracle.config.refresh_interval).await;
+                if let Err(err) = oracle.refresh().await {
+                    tracing::warn!(target: COUNTER_CLIENT, "fee refresh failed with err: {}", err);
+                }
+            }
+        })
+    }
+
+    /// Sets the current fees and the gas limit on `tx`.
+    pub async fn prepare(&self, signer: &SignerProvider, tx: &mut TypedTransaction) -> anyhow::Result<()> {
+        self.current().apply(tx);
+        let gas = match self.config.gas {
+            GasPolicy::Fixed(gas) => U256::from(gas),
+            GasPolicy::Estimate { margin } => {
+                tx.set_from(signer.inner().address());
+                // A reverting call fails the estimate, report why.
+                let estimate = signer
+                    .estimate_gas(tx, None)
+                    .await
+                    .map_err(|err| anyhow::anyhow!("eth_estimateGas: {}", revert::describe_error(&err)))?;
+                U256::from((estimate.as_u128() as f64 * margin).ceil() as u128)
+            }
+        };
+        tx.set_gas(gas);
+        Ok(())
+    }
+}
+
+async fn compute(
+    provider: &Provider<Transport>,
+    chain: &ChainCapabilities,
+    config: &FeeConfig,
+) -> Result<Fees, ChainError> {
+    if let FeeStrategy::Legacy {
+        gas_price: Some(gas_price),
+    } = config.strategy
+    {
+        return Ok(Fees::Legacy { gas_price });
+    }
+    let base_fee = match chain::latest_base_fee(provider).await? {
+        Some(base_fee) if chain.london => base_fee,
+        _ => {
+            return Ok(Fees::Legacy {
+                gas_price: chain::gas_price(provider).await?,
+            })
+        }
+    };
+    let max_fee = |priority_fee| scale(base_fee, config.base_fee_multiplier) + priority_fee;
+    let fees = match config.strategy {
+        FeeStrategy::Legacy { .. } => Fees::Legacy {
+            gas_price: scale(base_fee, config.base_fee_multiplier),
+        },
+        FeeStrategy::Eip1559 {
+            max_fee: fixed,
+            priority_fee,
+        } => {
+            let priority_fee = match priority_fee {
+                Some(priority_fee) => priority_fee,
+                None if chain.max_priority_fee => chain::max_priority_fee(provider).await?,
+                None => U256::zero(),
+            };
+            match fixed {
+                Some(fixed) => Fees::Eip1559 {
+                    max_fee: fixed,
+                    priority_fee: priority_fee.min(fixed),
+                },
+                None => Fees::Eip1559 {
+                    max_fee: max_fee(priority_fee),
+                    priority_fee,
+                },
+            }
+        }
+        FeeStrategy::FeeHistory { blocks, percentile } => {
+            let history = provider
+                .fee_history(blocks, BlockNumber::Latest, &[percentile])
+                .await
+                .map_err(ChainError::rpc("eth_feeHistory"))?;
+            // The last entry is the base fee of the next block.
+            let base_fee = *history
+                .base_fee_per_gas
+                .last()
+                .ok_or(ChainError::EmptyFeeHistory)?;
+            let mut rewards: Vec<U256> = history
+                .reward
+                .iter()
+                .filter_map(|block| block.first().copied())
+                .collect();
+            rewards.sort();
+            let priority_fee = rewards.get(rewards.len() / 2).copied().unwrap_or_default();
+            Fees::Eip1559 {
+                max_fee: scale(base_fee, config.base_fee_multiplier) + priority_fee,
+                priority_fee,
+            }
+        }
+    };
+    Ok(fees)
+}
+
+fn scale(value: U256, multiplier: f64) -> U256 {
+    U256::from((value.as_u128() as f64 * multiplier).ceil() as u128)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eip1559(max_fee: u64, priority_fee: u64) -> Fees {
+        Fees::Eip1559 {
+            max_fee: max_fee.into(),
+            priority_fee: priority_fee.into(),
+        }
+    }
+
+    #[test]
+    fn bumps_round_up() {
+        let legacy = Fees::Legacy {
+            gas_price: 1_000.into(),
+        };
+        assert_eq!(
+            legacy.bumped(10),
+            Fees::Legacy {
+                gas_price: 1_100.into()
+            }
+        );
+        // 10% of 1 wei still raises it, nodes reject a replacement that
+        // does not.
+        assert_eq!(eip1559(100, 1).bumped(10), eip1559(110, 2));
+        assert_eq!(eip1559(100, 0).bumped(10), eip1559(110, 0));
+        assert_eq!(eip1559(100, 1).bumped(0), eip1559(100, 1));
+    }
+
+    #[test]
+    fn raises_fees_to_the_floor() {
+        assert_eq!(eip1559(100, 5).at_least(eip1559(80, 7)), eip1559(100, 7));
+        assert_eq!(eip1559(100, 5).at_least(eip1559(120, 2)), eip1559(120, 5));
+        let legacy = |gas_price: u64| Fees::Legacy {
+            gas_price: gas_price.into(),
+        };
+        assert_eq!(legacy(50).at_least(legacy(70)), legacy(70));
+        assert_eq!(legacy(50).at_least(legacy(30)), legacy(50));
+        // A floor of the other type cannot be compared.
+        assert_eq!(eip1559(100, 5).at_least(legacy(500)), eip1559(100, 5));
+        assert_eq!(legacy(50).at_least(eip1559(500, 50)), legacy(50));
+    }
+}
@@ -0,0 +1,207 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use ethers::{
+    providers::{Middleware, Provider, StreamExt},
+    types::TxHash,
+};
+use tokio::{sync::Notify, task::JoinHandle, time::Instant};
+
+use crate::{transport::Transport, COUNTER_CLIENT};
+
+/// How long a transaction nobody tracks is kept, it may be one of the run
+/// whose submission has not returned yet.
+const UNTRACKED_TTL: Duration = Duration::from_secs(30);
+
+struct Seen {
+    /// Transactions of the run, kept until the watcher is dropped.
+    tracked: HashSet<TxHash>,
+    /// When the head carrying the transaction arrived.
+    included: HashMap<TxHash, Instant>,
+    /// When the node announced the transaction as pending.
+    pending: HashMap<TxHash, Instant>,
+    pruned_at: Instant,
+}
+
+impl Default for Seen {
+    fn default() -> Self {
+        Self {
+            tracked: HashSet::new(),
+            included: HashMap::new(),
+            pending: HashMap::new(),
+            pruned_at: Instant::now(),
+        }
+    }
+}
+
+impl Seen {
+    /// Drops the transactions of other senders once they are too old to be
+    /// ours, at most once a second.
+    fn prune(&mut self) {
+        let now = Instant::now();
+        if now - self.pruned_at < Duration::from_secs(1) {
+            return;
+        }
+        self.pruned_at = now;
+        let tracked = &self.tracked;
+        let keep =
+            |hash: &TxHash, at: &mut Instant| tracked.contains(hash) || now - *at < UNTRACKED_TTL;
+        self.included.retain(keep);
+        self.pending.retain(keep);
+    }
+}
+
+/// Timestamps inclusion from `newHeads` notifications, and optionally
+/// mempool arrival from `newPendingTransactions`, so latencies don't carry
+/// the receipt polling interval.
+pub struct InclusionWatcher {
+    seen: Arc<Mutex<Seen>>,
+    new_head: Arc<Notify>,
+    tasks: Vec<JoinHandle<()>>,
+}
+
+impl InclusionWatcher {
+    /// `None` when the transport has no subscriptions.
+    pub fn spawn(provider: &Provider<Transport>, pending: bool) -> Option<Self> {
+        if !provider.as_ref().is_pubsub() {
+            return None;
+        }
+        let seen = Arc::new(Mutex::new(Seen::default()));
+        let new_head = Arc::new(Notify::new());
+        let mut tasks = vec![tokio::spawn(watch_heads(
+            provider.clone(),
+            seen.clone(),
+            new_head.clone(),
+        ))];
+        if pending {
+            tasks.push(tokio::spawn(watch_pending(provider.clone(), seen.clone())));
+        }
+        Some(Self {
+            seen,
+            new_head,
+            tasks,
+        })
+    }
+
+    /// A watcher whose head stream never delivers.
+    #[cfg(test)]
+    pub(crate) fn without_heads() -> Self {
+        Self {
+            seen: Default::default(),
+            new_head: Arc::new(Notify::new()),
+            tasks: vec![],
+        }
+    }
+
+    /// Keeps the timestamps of `hash` for the rest of the run, the ones of
+    /// other transactions are dropped after a while.
+    pub fn track(&self, hash: TxHash) {
+        self.seen.lock().unwrap().tracked.insert(hash);
+    }
+
+    pub fn included_at(&self, hash: &TxHash) -> Option<Instant> {
+        self.seen.lock().unwrap().included.get(hash).copied()
+    }
+
+    pub fn pending_at(&self, hash: &TxHash) -> Option<Instant> {
+        self.seen.lock().unwrap().pending.get(hash).copied()
+    }
+
+    /// Resolves on the next head.
+    pub async fn next_head(&self) {
+        self.new_head.notified().await
+    }
+}
+
+impl Drop for InclusionWatcher {
+    fn drop(&mut self) {
+        for task in &self.tasks {
+            task.abort();
+        }
+    }
+}
+
+async fn watch_heads(provider: Provider<Transport>, seen: Arc<Mutex<Seen>>, new_head: Arc<Notify>) {
+    let mut heads = match provider.subscribe_blocks().await {
+        Ok(heads) => heads,
+        Err(err) => {
+            tracing::warn!(target: COUNTER_CLIENT, "newHeads subscription failed with err: {}", err);
+            return;
+        }
+    };
+    while let Some(head) = heads.next().await {
+        let arrived = Instant::now();
+        let Some(hash) = head.hash else { continue };
+        // Heads come without their transactions.
+        match provider.get_block(hash).await {
+            Ok(Some(block)) => {
+                let mut seen = seen.lock().unwrap();
+                // Overwrite, a tx reorged into a later block counts from there.
+                for tx in block.transactions {
+                    seen.included.insert(tx, arrived);
+                }
+                seen.prune();
+            }
+            Ok(None) => {}
+            Err(err) => {
+                tracing::warn!(target: COUNTER_CLIENT, "block {:?} fetch failed with err: {}", hash, err);
+            }
+        }
+        new_head.notify_waiters();
+    }
+    tracing::warn!(target: COUNTER_CLIENT, "newHeads subscription closed");
+}
+
+async fn watch_pending(provider: Provider<Transport>, seen: Arc<Mutex<Seen>>) {
+    let mut txs = match provider.subscribe_pending_txs().await {
+        Ok(txs) => txs,
+        Err(err) => {
+            tracing::warn!(
+                target: COUNTER_CLIENT,
+                "newPendingTransactions subscription failed with err: {}",
+                err
+            );
+            return;
+        }
+    };
+    while let Some(tx) = txs.next().await {
+        let mut seen = seen.lock().unwrap();
+        seen.pending.entry(tx).or_insert_with(Instant::now);
+        seen.prune();
+    }
+    tracing::warn!(target: COUNTER_CLIENT, "newPendingTransactions subscription closed");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn keeps_only_tracked_transactions() {
+        let watcher = InclusionWatcher::without_heads();
+        let [ours, theirs, recent] = [1, 2, 3].map(TxHash::repeat_byte);
+        watcher.track(ours);
+        {
+            let mut seen = watcher.seen.lock().unwrap();
+            for hash in [ours, theirs] {
+                seen.included.insert(hash, Instant::now());
+                seen.pending.insert(hash, Instant::now());
+            }
+        }
+        tokio::time::advance(UNTRACKED_TTL).await;
+        {
+            let mut seen = watcher.seen.lock().unwrap();
+            seen.included.insert(recent, Instant::now());
+            seen.prune();
+        }
+        assert!(watcher.included_at(&ours).is_some());
+        assert!(watcher.pending_at(&ours).is_some());
+        assert!(watcher.included_at(&theirs).is_none());
+        assert!(watcher.pending_at(&theirs).is_none());
+        // May still turn out to be one of the run.
+        assert!(watcher.included_at(&recent).is_some());
+    }
+}
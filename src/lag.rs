@@ -0,0 +1,139 @@
+use std::{
+    collections::BTreeMap,
+    fmt,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use ethers::providers::{Middleware, Provider};
+use tokio::{task::JoinHandle, time::Instant};
+
+use crate::{config::redact_url, stats::Histogram, transport::Transport, COUNTER_CLIENT};
+
+/// When each block height was first reported by one endpoint.
+type Heights = Arc<Mutex<BTreeMap<u64, Instant>>>;
+
+/// Follows the head of the submit endpoint and of every read replica, to
+/// tell how long a block the sequencer has takes to show up on each replica.
+pub struct ReplicaLagProbe {
+    sequencer: Heights,
+    replicas: Vec<(String, Heights)>,
+    tasks: Vec<JoinHandle<()>>,
+}
+
+impl ReplicaLagProbe {
+    /// `None` unless the transport splits writes from reads.
+    pub fn spawn(transport: &Transport, interval: Duration) -> Option<Self> {
+        let write = transport.write()?;
+        let replicas: Vec<(String, Transport)> = match transport.read().pool() {
+            Some(pool) => pool
+                .endpoints()
+                .iter()
+                .map(|endpoint| (redact_url(&endpoint.url), endpoint.transport().clone()))
+                .collect(),
+            None => vec![("read".to_string(), transport.read().clone())],
+        };
+
+        let sequencer = Heights::default();
+        let mut tasks = vec![tokio::spawn(follow_head(
+            Provider::new(write.clone()),
+            sequencer.clone(),
+            interval,
+        ))];
+        let replicas = replicas
+            .into_iter()
+            .map(|(name, transport)| {
+                let heights = Heights::default();
+                tasks.push(tokio::spawn(follow_head(
+                    Provider::new(transport),
+                    heights.clone(),
+                    interval,
+                )));
+                (name, heights)
+            })
+            .collect();
+        Some(Self {
+            sequencer,
+            replicas,
+            tasks,
+        })
+    }
+
+    /// Lag of every replica over `blocks`, heights the sequencer never
+    /// reported while probing are skipped.
+    pub fn report(&self, blocks: impl IntoIterator<Item = u64>) -> ReplicaLag {
+        let sequencer = self.sequencer.lock().unwrap();
+        let seen: Vec<(u64, Instant)> = blocks
+            .into_iter()
+            .filter_map(|block| Some((block, *sequencer.get(&block)?)))
+            .collect();
+        let replicas = self
+            .replicas
+            .iter()
+            .map(|(name, heights)| {
+                let heights = heights.lock().unwrap();
+                let mut lags = vec![];
+                let mut missing = 0;
+                for (block, at) in &seen {
+                    match heights.get(block) {
+                        Some(replica_at) => lags.push(replica_at.saturating_duration_since(*at)),
+                        None => missing += 1,
+                    }
+                }
+                (name.clone(), Histogram::new(lags), missing)
+            })
+            .collect();
+        ReplicaLag {
+            blocks: seen.len(),
+            replicas,
+        }
+    }
+}
+
+impl Drop for ReplicaLagProbe {
+    fn drop(&mut self) {
+        for task in &self.tasks {
+            task.abort();
+        }
+    }
+}
+
+async fn follow_head(provider: Provider<Transport>, heights: Heights, interval: Duration) {
+    let mut last = None;
+    loop {
+        match provider.get_block_number().await {
+            Ok(head) => {
+                let head = head.as_u64();
+                let now = Instant::now();
+                // Heights skipped between two polls count from this one.
+                let from = last.map_or(head, |last: u64| last + 1);
+                let mut heights = heights.lock().unwrap();
+                for block in from..=head {
+                    heights.entry(block).or_insert(now);
+                }
+                last = Some(last.map_or(head, |last| last.max(head)));
+            }
+            Err(err) => tracing::debug!(target: COUNTER_CLIENT, "head poll failed with err: {}", err),
+        }
+        tokio::time::sleep(interval).await;
+    }
+}
+
+/// Per-replica delay behind the submit endpoint over the blocks of a run.
+#[derive(Debug, Clone)]
+pub struct ReplicaLag {
+    pub blocks: usize,
+    /// Name, lag samples, and blocks the replica never reported.
+    pub replicas: Vec<(String, Histogram, usize)>,
+}
+
+impl fmt::Display for ReplicaLag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "==== replica lag over {} blocks ====", self.blocks)?;
+        for (name, lag, missing) in &self.replicas {
+            writeln!(f, "{} (never seen: {}):", name, missing)?;
+            write!(f, "{}", lag)?;
+        }
+        Ok(())
+    }
+}
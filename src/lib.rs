@@ -0,0 +1,120 @@
+//! Client stack for the `Lock` counter contract: multi-endpoint providers
+//! over http, ws and ipc, the operator signer, the `Lock` bindings, and the
+//! load, tracking and reporting machinery of the `ethers-counter` binary.
+//!
+//! [`client::CounterClient`] is the entry point for reading and driving one
+//! deployed contract.
+
+pub mod bindings;
+pub mod chain;
+pub mod client;
+pub mod config;
+pub mod endpoints;
+pub mod export;
+pub mod fees;
+pub mod inclusion;
+pub mod lag;
+pub mod load;
+#[cfg(test)]
+mod mock_rpc;
+pub mod nonce;
+pub mod replace;
+pub mod revert;
+pub mod secret;
+pub mod signer;
+pub mod stats;
+pub mod submit_error;
+pub mod supervisor;
+pub mod tracker;
+pub mod transport;
+pub mod verify;
+pub mod wallet_pool;
+pub mod watch;
+pub mod workload;
+
+pub use client::{ClientConfig, Connection, CounterClient};
+
+use crate::{
+    chain::ChainError,
+    config::redact_url,
+    endpoints::{EndpointConfig, EndpointPool},
+    signer::AccountSigner,
+    transport::Transport,
+};
+use ethers::{
+    middleware::{NonceManagerMiddleware, SignerMiddleware},
+    providers::{JsonRpcClient, Provider},
+    signers::{LocalWallet, Signer},
+};
+
+type Provider0<T = Transport> = Provider<T>;
+type Provider1<T = Transport> = SignerMiddleware<Provider0<T>, AccountSigner>;
+type Provider2<T = Transport> = NonceManagerMiddleware<Provider1<T>>;
+/// Provider with the account's signer and a nonce manager on top.
+pub type SignerProvider<T = Transport> = Provider2<T>;
+pub type SignerWithoutNonceProvider<T = Transport> = Provider1<T>;
+
+/// Tracing target of everything this crate logs.
+pub const COUNTER_CLIENT: &str = "counter_client_context";
+
+pub fn remove_0x_prefix(input: &str) -> String {
+    if input.starts_with("0x") || input.starts_with("0X") {
+        input[2..].to_string()
+    } else {
+        input.to_string()
+    }
+}
+
+pub fn get_signer_provider<T: JsonRpcClient>(
+    provider: Provider<T>,
+    chain_id: u64,
+    signer: AccountSigner,
+) -> SignerProvider<T> {
+    let signer = signer.with_chain_id(chain_id);
+    let address = signer.address();
+    let signer_provider = SignerMiddleware::new(provider, signer);
+    NonceManagerMiddleware::new(signer_provider, address)
+}
+
+pub fn get_wallet_signer_provider<T: JsonRpcClient>(
+    provider: Provider<T>,
+    chain_id: u64,
+    wallet: LocalWallet,
+) -> SignerProvider<T> {
+    get_signer_provider(provider, chain_id, wallet.into())
+}
+
+/// Connects over http(s), ws(s) or ipc depending on the url.
+pub async fn connect_evm_rpc(rpc: &str) -> Result<Provider<Transport>, ChainError> {
+    let transport = Transport::connect(rpc).await.map_err(|source| ChainError::Connect {
+        url: redact_url(rpc),
+        source,
+    })?;
+    Ok(Provider::new(transport))
+}
+
+/// Connects every endpoint into one routed, retrying, failing-over pool.
+pub async fn connect_evm_rpcs(
+    rpcs: &[String],
+    config: EndpointConfig,
+) -> Result<Provider<Transport>, ChainError> {
+    let pool = EndpointPool::connect(rpcs, config)
+        .await
+        .map_err(|source| ChainError::Connect {
+            url: rpcs
+                .iter()
+                .map(|rpc| redact_url(rpc))
+                .collect::<Vec<_>>()
+                .join(","),
+            source,
+        })?;
+    Ok(Provider::new(Transport::Pool(pool)))
+}
+
+/// Sends through `write` and reads through `read`.
+pub fn split_evm_rpc(read: Provider<Transport>, write: Provider<Transport>) -> Provider<Transport> {
+    Provider::new(Transport::Split {
+        read: Box::new(read.as_ref().clone()),
+        write: Box::new(write.as_ref().clone()),
+    })
+}
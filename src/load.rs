@@ -0,0 +1,432 @@
+use std::{fmt, str::FromStr, time::Duration};
+
+use anyhow::{anyhow, bail};
+use tokio::time::Instant;
+
+/// Longest the scheduler sleeps before re-reading the rate, so ramps take
+/// effect as they go.
+const IDLE_STEP: Duration = Duration::from_millis(100);
+
+/// Rounding slack when a send falls due.
+const CREDIT_EPSILON: f64 = 1e-9;
+
+/// Named load profiles, picked with `--profile`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoadPreset {
+    /// Ten unthrottled transactions, the historical behaviour of this tool.
+    Smoke,
+    /// One minute ramp-up to 10 tps, ten minutes steady, one minute ramp-down.
+    Soak,
+}
+
+impl FromStr for LoadPreset {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "smoke" => Ok(LoadPreset::Smoke),
+            "soak" => Ok(LoadPreset::Soak),
+            _ => bail!("unknown load profile `{}`, expected `smoke` or `soak`", s),
+        }
+    }
+}
+
+/// A ramp stage: the send rate moves linearly from the previous stage's
+/// target to `target_tps` over `duration`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Stage {
+    pub duration: Duration,
+    pub target_tps: f64,
+}
+
+impl FromStr for Stage {
+    type Err = anyhow::Error;
+
+    /// Parses `<duration>:<tps>`, e.g. `30s:50`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (duration, tps) = s
+            .split_once(':')
+            .ok_or_else(|| anyhow!("invalid stage `{}`, expected `<duration>:<tps>`", s))?;
+        let target_tps: f64 = tps.trim().parse()?;
+        if !(target_tps >= 0.0 && target_tps.is_finite()) {
+            bail!("invalid stage `{}`, tps must be a non-negative number", s);
+        }
+        Ok(Stage {
+            duration: parse_duration(duration)?,
+            target_tps,
+        })
+    }
+}
+
+/// Burst mode: `size` transactions back-to-back every `interval`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Burst {
+    pub size: u64,
+    pub interval: Duration,
+}
+
+impl FromStr for Burst {
+    type Err = anyhow::Error;
+
+    /// Parses `<size>/<interval>`, e.g. `50/5s`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (size, interval) = s
+            .split_once('/')
+            .ok_or_else(|| anyhow!("invalid burst `{}`, expected `<size>/<interval>`", s))?;
+        let size: u64 = size.trim().parse()?;
+        if size == 0 {
+            bail!("invalid burst `{}`, size must be positive", s);
+        }
+        Ok(Burst {
+            size,
+            interval: parse_duration(interval)?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LoadProfile {
+    /// Stop after this many transactions.
+    pub total_txs: Option<u64>,
+    /// Constant send rate; `None` sends as fast as the workload allows.
+    pub tps: Option<f64>,
+    /// Stop after this much wall-clock time.
+    pub duration: Option<Duration>,
+    /// Ramp stages; when set they replace `tps` and bound the run duration.
+    pub stages: Vec<Stage>,
+    pub burst: Option<Burst>,
+}
+
+impl LoadProfile {
+    pub fn preset(preset: LoadPreset) -> Self {
+        match preset {
+            LoadPreset::Smoke => LoadProfile {
+                total_txs: Some(10),
+                ..Default::default()
+            },
+            LoadPreset::Soak => LoadProfile {
+                stages: vec![
+                    Stage {
+                        duration: Duration::from_secs(60),
+                        target_tps: 10.0,
+                    },
+                    Stage {
+                        duration: Duration::from_secs(600),
+                        target_tps: 10.0,
+                    },
+                    Stage {
+                        duration: Duration::from_secs(60),
+                        target_tps: 0.0,
+                    },
+                ],
+                ..Default::default()
+            },
+        }
+    }
+
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.total_txs.is_none() && self.run_duration().is_none() {
+            bail!("load profile is unbounded, set a tx count, a duration or stages");
+        }
+        if self.burst.is_some() && (self.tps.is_some() || !self.stages.is_empty()) {
+            bail!("burst mode cannot be combined with a target tps or stages");
+        }
+        if let Some(tps) = self.tps {
+            if !(tps > 0.0 && tps.is_finite()) {
+                bail!("target tps must be a positive number, got {}", tps);
+            }
+        }
+        Ok(())
+    }
+
+    /// Wall-clock limit of the run, if any.
+    pub fn run_duration(&self) -> Option<Duration> {
+        let staged = if self.stages.is_empty() {
+            None
+        } else {
+            Some(self.stages.iter().map(|s| s.duration).sum::<Duration>())
+        };
+        match (staged, self.duration) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        }
+    }
+
+    /// Target send rate at `elapsed` into the run, `None` meaning unthrottled.
+    pub fn rate_at(&self, elapsed: Duration) -> Option<f64> {
+        if self.stages.is_empty() {
+            return self.tps;
+        }
+
+        let mut from = self.tps.unwrap_or(0.0);
+        let mut offset = Duration::ZERO;
+        for stage in &self.stages {
+            if elapsed < offset + stage.duration {
+                let progress =
+                    (elapsed - offset).as_secs_f64() / stage.duration.as_secs_f64().max(f64::EPSILON);
+                return Some(from + (stage.target_tps - from) * progress);
+            }
+            from = stage.target_tps;
+            offset += stage.duration;
+        }
+        Some(from)
+    }
+}
+
+impl fmt::Display for LoadProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = vec![];
+        if let Some(total) = self.total_txs {
+            parts.push(format!("txs={}", total));
+        }
+        if let Some(duration) = self.duration {
+            parts.push(format!("duration={:?}", duration));
+        }
+        if let Some(burst) = self.burst {
+            parts.push(format!("burst={}/{:?}", burst.size, burst.interval));
+        } else if !self.stages.is_empty() {
+            let stages: Vec<_> = self
+                .stages
+                .iter()
+                .map(|s| format!("{:?}:{}", s.duration, s.target_tps))
+                .collect();
+            parts.push(format!("stages={}", stages.join(",")));
+        } else if let Some(tps) = self.tps {
+            parts.push(format!("tps={}", tps));
+        } else {
+            parts.push("tps=unthrottled".to_string());
+        }
+        write!(f, "{}", parts.join(" "))
+    }
+}
+
+/// Paces a workload according to a [`LoadProfile`].
+pub struct Scheduler {
+    profile: LoadProfile,
+    started: Instant,
+    next_at: Instant,
+    sent: u64,
+    burst_left: u64,
+    /// Sends due under a throttled rate, the integral of the rate since
+    /// the last one.
+    credit: f64,
+    credited_until: Duration,
+}
+
+impl Scheduler {
+    pub fn new(profile: LoadProfile) -> Self {
+        let now = Instant::now();
+        Self {
+            profile,
+            started: now,
+            next_at: now,
+            sent: 0,
+            burst_left: 0,
+            // The first send goes out right away.
+            credit: 1.0,
+            credited_until: Duration::ZERO,
+        }
+    }
+
+    /// Waits until the next transaction is due and returns its index, or
+    /// `None` once the profile is exhausted.
+    pub async fn next(&mut self) -> Option<u64> {
+        loop {
+            if matches!(self.profile.total_txs, Some(total) if self.sent >= total) {
+                return None;
+            }
+            let now = Instant::now();
+            let elapsed = now - self.started;
+            if matches!(self.profile.run_duration(), Some(limit) if elapsed >= limit) {
+                return None;
+            }
+
+            if let Some(burst) = self.profile.burst {
+                if self.burst_left == 0 {
+                    if now < self.next_at {
+                        self.sleep_until(self.next_at).await;
+                        continue;
+                    }
+                    self.next_at += burst.interval;
+                    self.burst_left = burst.size;
+                }
+                self.burst_left -= 1;
+                return Some(self.emit());
+            }
+
+            let Some(rate) = self.profile.rate_at(elapsed) else {
+                return Some(self.emit());
+            };
+            // Trapezoids are exact on a linear ramp, the steps are short
+            // enough for the bends between stages.
+            let previous = self.profile.rate_at(self.credited_until).unwrap_or(rate);
+            let step = (elapsed - self.credited_until).as_secs_f64();
+            // Never let a slow workload build up a backlog of catch-up sends.
+            self.credit = (self.credit + (previous + rate) / 2.0 * step).min(1.0);
+            self.credited_until = elapsed;
+            if self.credit >= 1.0 - CREDIT_EPSILON {
+                self.credit -= 1.0;
+                return Some(self.emit());
+            }
+            let wait = if rate > 0.0 {
+                Duration::from_secs_f64((1.0 - self.credit) / rate)
+                    .clamp(Duration::from_micros(1), IDLE_STEP)
+            } else {
+                IDLE_STEP
+            };
+            self.sleep_until(now + wait).await;
+        }
+    }
+
+    pub fn sent(&self) -> u64 {
+        self.sent
+    }
+
+    fn emit(&mut self) -> u64 {
+        let idx = self.sent;
+        self.sent += 1;
+        idx
+    }
+
+    /// Sleeps until `deadline`, cut short by the end of the run.
+    async fn sleep_until(&self, deadline: Instant) {
+        let deadline = match self.profile.run_duration() {
+            Some(limit) => deadline.min(self.started + limit),
+            None => deadline,
+        };
+        tokio::time::sleep_until(deadline).await;
+    }
+}
+
+/// Parses `500ms`, `30s`, `5m`, `1h`; a bare number is taken as seconds.
+pub fn parse_duration(input: &str) -> anyhow::Result<Duration> {
+    let input = input.trim();
+    let split = input
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(input.len());
+    let (value, unit) = input.split_at(split);
+    let value: f64 = value
+        .parse()
+        .map_err(|_| anyhow!("invalid duration `{}`", input))?;
+    let secs = match unit.trim() {
+        "" | "s" => value,
+        "ms" => value / 1000.0,
+        "m" => value * 60.0,
+        "h" => value * 3600.0,
+        _ => bail!("invalid duration unit in `{}`", input),
+    };
+    Ok(Duration::from_secs_f64(secs))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn staged(stages: &[(u64, f64)]) -> LoadProfile {
+        LoadProfile {
+            stages: stages
+                .iter()
+                .map(|&(secs, target_tps)| Stage {
+                    duration: Duration::from_secs(secs),
+                    target_tps,
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    /// Drains the scheduler in paused time.
+    async fn emitted(profile: LoadProfile) -> u64 {
+        let mut scheduler = Scheduler::new(profile);
+        while scheduler.next().await.is_some() {}
+        scheduler.sent()
+    }
+
+    #[test]
+    fn rate_follows_the_stages() {
+        let profile = staged(&[(10, 10.0), (10, 10.0), (10, 0.0)]);
+        let rate = |secs: f64| profile.rate_at(Duration::from_secs_f64(secs)).unwrap();
+        assert_eq!(rate(0.0), 0.0);
+        assert_eq!(rate(5.0), 5.0);
+        assert_eq!(rate(15.0), 10.0);
+        assert_eq!(rate(25.0), 5.0);
+        assert_eq!(rate(40.0), 0.0);
+    }
+
+    #[test]
+    fn rate_without_stages() {
+        assert_eq!(LoadProfile::default().rate_at(Duration::ZERO), None);
+        let constant = LoadProfile {
+            tps: Some(3.0),
+            ..Default::default()
+        };
+        assert_eq!(constant.rate_at(Duration::from_secs(100)), Some(3.0));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn ramp_from_zero_sends_its_integral() {
+        // 0 to 10 tps over a minute is 300 txs, plus the one at the start.
+        let sent = emitted(staged(&[(60, 10.0)])).await;
+        assert!((299..=302).contains(&sent), "{}", sent);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn soak_preset_sends_its_integral() {
+        let sent = emitted(LoadProfile::preset(LoadPreset::Soak)).await;
+        assert!((6595..=6605).contains(&sent), "{}", sent);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn constant_rate() {
+        let profile = LoadProfile {
+            tps: Some(10.0),
+            duration: Some(Duration::from_secs(2)),
+            ..Default::default()
+        };
+        let sent = emitted(profile).await;
+        assert!((20..=21).contains(&sent), "{}", sent);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn bursts_every_interval() {
+        let profile = LoadProfile {
+            duration: Some(Duration::from_secs(3)),
+            burst: Some("5/1s".parse().unwrap()),
+            ..Default::default()
+        };
+        assert_eq!(emitted(profile).await, 15);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn tx_count_stops_an_unthrottled_run() {
+        assert_eq!(emitted(LoadProfile::preset(LoadPreset::Smoke)).await, 10);
+    }
+
+    #[test]
+    fn parses_durations() {
+        let parse = |input| parse_duration(input).unwrap();
+        assert_eq!(parse("500ms"), Duration::from_millis(500));
+        assert_eq!(parse("30s"), Duration::from_secs(30));
+        assert_eq!(parse("1.5s"), Duration::from_millis(1500));
+        assert_eq!(parse("5m"), Duration::from_secs(300));
+        assert_eq!(parse("1h"), Duration::from_secs(3600));
+        assert_eq!(parse(" 2 "), Duration::from_secs(2));
+        for invalid in ["", "s", "5x", "ten", "1.2.3s"] {
+            assert!(parse_duration(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn parses_stages_and_bursts() {
+        assert_eq!(
+            "30s:50".parse::<Stage>().unwrap(),
+            Stage {
+                duration: Duration::from_secs(30),
+                target_tps: 50.0,
+            }
+        );
+        assert!("30s:-1".parse::<Stage>().is_err());
+        assert!("30s".parse::<Stage>().is_err());
+        assert!("0/5s".parse::<Burst>().is_err());
+    }
+}
@@ -0,0 +1,137 @@
+//! A JSON-RPC endpoint over plain http for tests, answering every request
+//! from a closure.
+
+use std::sync::Arc;
+
+use ethers::providers::Provider;
+use serde_json::{json, Value};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+use crate::transport::Transport;
+
+/// Result of a call, or a JSON-RPC error with this message.
+pub type Reply = Result<Value, String>;
+
+/// Serves on a free local port until the test runtime shuts down.
+pub async fn spawn<F>(handler: F) -> String
+where
+    F: Fn(&str, &Value) -> Reply + Send + Sync + 'static,
+{
+    spawn_on("127.0.0.1:0", handler).await
+}
+
+/// A local address nothing listens on, connections to it are refused until
+/// [`spawn_on`] serves there.
+pub async fn free_addr() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    listener.local_addr().unwrap().to_string()
+}
+
+/// Serves on `addr` until the test runtime shuts down.
+pub async fn spawn_on<F>(addr: &str, handler: F) -> String
+where
+    F: Fn(&str, &Value) -> Reply + Send + Sync + 'static,
+{
+    let listener = TcpListener::bind(addr).await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let handler = Arc::new(handler);
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            tokio::spawn(serve(stream, handler.clone()));
+        }
+    });
+    url
+}
+
+pub async fn provider<F>(handler: F) -> Provider<Transport>
+where
+    F: Fn(&str, &Value) -> Reply + Send + Sync + 'static,
+{
+    let url = spawn(handler).await;
+    Provider::new(Transport::connect(&url).await.unwrap())
+}
+
+async fn serve<F>(mut stream: TcpStream, handler: Arc<F>)
+where
+    F: Fn(&str, &Value) -> Reply,
+{
+    let mut buf = vec![];
+    loop {
+        // Requests on a kept-alive connection arrive one at a time.
+        let header_end = loop {
+            if let Some(end) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+                break end + 4;
+            }
+            if !read_more(&mut stream, &mut buf).await {
+                return;
+            }
+        };
+        let headers = String::from_utf8_lossy(&buf[..header_end]).to_ascii_lowercase();
+        let length: usize = headers
+            .lines()
+            .find_map(|line| line.strip_prefix("content-length:"))
+            .and_then(|len| len.trim().parse().ok())
+            .unwrap_or(0);
+        while buf.len() < header_end + length {
+            if !read_more(&mut stream, &mut buf).await {
+                return;
+            }
+        }
+        let request: Value = serde_json::from_slice(&buf[header_end..header_end + length]).unwrap();
+        buf.drain(..header_end + length);
+
+        let method = request["method"].as_str().unwrap_or_default();
+        let response = match handler(method, &request["params"]) {
+            Ok(result) => json!({"jsonrpc": "2.0", "id": request["id"], "result": result}),
+            Err(message) => json!({
+                "jsonrpc": "2.0",
+                "id": request["id"],
+                "error": {"code": -32000, "message": message},
+            }),
+        };
+        let body = response.to_string();
+        // One write, a split one waits out the client's delayed ack.
+        let reply = format!(
+            "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        );
+        if stream.write_all(reply.as_bytes()).await.is_err() {
+            return;
+        }
+    }
+}
+
+async fn read_more(stream: &mut TcpStream, buf: &mut Vec<u8>) -> bool {
+    let mut chunk = [0; 4096];
+    match stream.read(&mut chunk).await {
+        Ok(0) | Err(_) => false,
+        Ok(n) => {
+            buf.extend_from_slice(&chunk[..n]);
+            true
+        }
+    }
+}
+
+/// A successful receipt of `hash` in block `block`.
+pub fn receipt(hash: &Value, block: u64) -> Value {
+    json!({
+        "transactionHash": hash,
+        "transactionIndex": "0x0",
+        "blockHash": format!("0x{:064x}", block),
+        "blockNumber": format!("{:#x}", block),
+        "from": format!("0x{:040x}", 1),
+        "to": format!("0x{:040x}", 2),
+        "cumulativeGasUsed": "0x5208",
+        "gasUsed": "0x5208",
+        "contractAddress": null,
+        "logs": [],
+        "logsBloom": format!("0x{}", "00".repeat(256)),
+        "status": "0x1",
+        "type": "0x2",
+        "effectiveGasPrice": "0x1",
+    })
+}
@@ -0,0 +1,150 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::{Arc, Mutex},
+};
+
+use ethers::{
+    providers::Middleware,
+    types::{transaction::eip2718::TypedTransaction, BlockId, BlockNumber, TxHash, U256},
+};
+use tokio::time::Instant;
+
+use crate::SignerProvider;
+
+/// A nonce handed out by [`NonceAllocator::next`].
+#[derive(Debug, Clone)]
+pub enum Slot {
+    /// Its submission has not returned yet.
+    InFlight,
+    /// Accepted by the node, kept to re-send it.
+    Sent {
+        tx: Box<TypedTransaction>,
+        hash: TxHash,
+        at: Instant,
+    },
+}
+
+#[derive(Default)]
+struct Nonces {
+    next: U256,
+    /// Nonces of rejected submissions, handed out again first.
+    holes: BTreeSet<U256>,
+    /// Nonces handed out and not yet known to be mined.
+    slots: BTreeMap<U256, Slot>,
+}
+
+/// Hands out nonces for one account, re-issuing nonces of rejected
+/// submissions first so that pipelined sends never leave a gap behind.
+pub struct NonceAllocator {
+    client: Arc<SignerProvider>,
+    nonces: Mutex<Nonces>,
+}
+
+impl NonceAllocator {
+    pub fn new(client: Arc<SignerProvider>) -> Self {
+        Self {
+            client,
+            nonces: Default::default(),
+        }
+    }
+
+    pub fn client(&self) -> &Arc<SignerProvider> {
+        &self.client
+    }
+
+    /// Loads the pending nonce of the account.
+    pub async fn init(&self) -> anyhow::Result<U256> {
+        let pending = self.count(BlockNumber::Pending).await?;
+        self.nonces.lock().unwrap().next = pending;
+        Ok(pending)
+    }
+
+    pub fn next(&self) -> U256 {
+        let mut nonces = self.nonces.lock().unwrap();
+        let nonce = match nonces.holes.pop_first() {
+            Some(hole) => hole,
+            None => {
+                let next = nonces.next;
+                nonces.next = next + 1;
+                next
+            }
+        };
+        nonces.slots.insert(nonce, Slot::InFlight);
+        nonce
+    }
+
+    /// Returns a nonce whose transaction never reached the node.
+    pub fn release(&self, nonce: U256) {
+        let mut nonces = self.nonces.lock().unwrap();
+        nonces.slots.remove(&nonce);
+        nonces.holes.insert(nonce);
+    }
+
+    /// Records the transaction the node accepted at `nonce`.
+    pub fn sent(&self, nonce: U256, tx: TypedTransaction, hash: TxHash) {
+        let slot = Slot::Sent {
+            tx: Box::new(tx),
+            hash,
+            at: Instant::now(),
+        };
+        self.nonces.lock().unwrap().slots.insert(nonce, slot);
+    }
+
+    /// Stops following a nonce whose fate is unknown, e.g. after a timed
+    /// out submission; the supervisor fills it if it turns into a gap.
+    pub fn forget(&self, nonce: U256) {
+        self.nonces.lock().unwrap().slots.remove(&nonce);
+    }
+
+    /// Moves past every nonce the node already counts as used, e.g. by txs
+    /// sent from elsewhere, keeping `rejected` for reuse if it is still free.
+    /// Nonces handed out and in flight are left alone.
+    pub async fn resync(&self, rejected: U256) -> anyhow::Result<U256> {
+        let pending = self.count(BlockNumber::Pending).await?;
+        let mut nonces = self.nonces.lock().unwrap();
+        nonces.holes = nonces.holes.split_off(&pending);
+        if rejected >= pending {
+            nonces.slots.remove(&rejected);
+            nonces.holes.insert(rejected);
+        }
+        if nonces.next < pending {
+            nonces.next = pending;
+        }
+        Ok(pending)
+    }
+
+    /// Drops the slots below `latest`, their transactions are mined.
+    pub fn mined(&self, latest: U256) {
+        let mut nonces = self.nonces.lock().unwrap();
+        nonces.slots = nonces.slots.split_off(&latest);
+        nonces.holes = nonces.holes.split_off(&latest);
+    }
+
+    /// The next fresh nonce, everything below was handed out.
+    pub fn peek(&self) -> U256 {
+        self.nonces.lock().unwrap().next
+    }
+
+    pub fn slot(&self, nonce: U256) -> Option<Slot> {
+        self.nonces.lock().unwrap().slots.get(&nonce).cloned()
+    }
+
+    /// Claims `nonce` unless a submission holds it, so the caller can fill it.
+    pub fn claim(&self, nonce: U256) -> bool {
+        let mut nonces = self.nonces.lock().unwrap();
+        if nonce >= nonces.next || nonces.slots.contains_key(&nonce) {
+            return false;
+        }
+        nonces.holes.remove(&nonce);
+        nonces.slots.insert(nonce, Slot::InFlight);
+        true
+    }
+
+    pub async fn count(&self, block: BlockNumber) -> anyhow::Result<U256> {
+        let address = self.client.inner().address();
+        Ok(self
+            .client
+            .get_transaction_count(address, Some(BlockId::Number(block)))
+            .await?)
+    }
+}
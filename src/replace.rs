@@ -0,0 +1,101 @@
+use std::{fmt, str::FromStr};
+
+use anyhow::bail;
+use ethers::{
+    providers::{Middleware, Provider, RpcError},
+    types::{
+        transaction::eip2718::TypedTransaction, Address, Transaction, TransactionRequest, U256,
+    },
+};
+
+use crate::{fees::Fees, transport::Transport, COUNTER_CLIENT};
+
+/// Gas of a plain transfer, what a cancellation or a gap filler costs.
+pub const TRANSFER_GAS: u64 = 21_000;
+
+/// How a pending transaction is pushed out of the way of its account.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplaceAction {
+    /// The same transaction with bumped fees.
+    SpeedUp,
+    /// A zero-value self-transfer with bumped fees, the original call is dropped.
+    Cancel,
+}
+
+impl fmt::Display for ReplaceAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplaceAction::SpeedUp => f.write_str("speed-up"),
+            ReplaceAction::Cancel => f.write_str("cancel"),
+        }
+    }
+}
+
+impl FromStr for ReplaceAction {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "speed-up" | "speedup" => Ok(ReplaceAction::SpeedUp),
+            "cancel" => Ok(ReplaceAction::Cancel),
+            _ => bail!("unknown action `{}`, expected `speed-up` or `cancel`", s),
+        }
+    }
+}
+
+/// A zero-value transfer from `from` to itself at `nonce`, without fees.
+pub fn self_transfer(from: Address, nonce: U256) -> TypedTransaction {
+    TransactionRequest::new()
+        .from(from)
+        .to(from)
+        .value(0)
+        .gas(TRANSFER_GAS)
+        .nonce(nonce)
+        .into()
+}
+
+/// The transaction that takes the place of `original` at `nonce`. Nodes
+/// only accept a replacement that outbids the original on every fee, so
+/// the original's fees are raised by `bump_percent`, and to `current` if the
+/// network moved further. Without fees on `original` `current` is bumped.
+pub fn replacement(
+    from: Address,
+    nonce: U256,
+    original: &TypedTransaction,
+    action: ReplaceAction,
+    current: Fees,
+    bump_percent: u64,
+) -> TypedTransaction {
+    let mut tx = match action {
+        ReplaceAction::SpeedUp => original.clone(),
+        ReplaceAction::Cancel => self_transfer(from, nonce),
+    };
+    let fees = match Fees::of(original) {
+        Some(fees) => fees.bumped(bump_percent).at_least(current),
+        None => current.bumped(bump_percent),
+    };
+    fees.apply(&mut tx);
+    tx
+}
+
+/// The transaction `from` has queued at `nonce`, looked up in the node's
+/// `txpool_content`. `None` if it is not there or the node does not serve
+/// the txpool namespace.
+pub async fn find_pending(
+    provider: &Provider<Transport>,
+    from: Address,
+    nonce: U256,
+) -> anyhow::Result<Option<Transaction>> {
+    let content = match provider.txpool_content().await {
+        Ok(content) => content,
+        Err(err) if err.as_error_response().is_some() => {
+            tracing::warn!(target: COUNTER_CLIENT, "txpool_content failed with err: {}", err);
+            return Ok(None);
+        }
+        Err(err) => return Err(err.into()),
+    };
+    let key = nonce.to_string();
+    Ok([content.pending, content.queued]
+        .into_iter()
+        .find_map(|mut pool| pool.get_mut(&from)?.remove(&key)))
+}
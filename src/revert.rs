@@ -0,0 +1,234 @@
+use std::fmt;
+
+use ethers::{
+    abi::{AbiDecode, Token},
+    contract::ContractError,
+    providers::{JsonRpcError, Middleware, MiddlewareError, Provider},
+    types::{
+        transaction::eip2718::TypedTransaction, BlockId, BlockNumber, Bytes, TransactionReceipt,
+        TransactionRequest, U256,
+    },
+};
+
+use crate::{bindings::lock::LOCK_ABI, transport::Transport};
+
+/// Selector of `Error(string)`, what `require` and `revert` with a message encode.
+const ERROR_SELECTOR: [u8; 4] = [0x08, 0xc3, 0x79, 0xa0];
+/// Selector of `Panic(uint256)`, raised by failed asserts and checked arithmetic.
+const PANIC_SELECTOR: [u8; 4] = [0x4e, 0x48, 0x7b, 0x71];
+
+/// Why a call or transaction reverted, decoded from its revert data.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Revert {
+    /// `require(cond, "...")` or `revert("...")`.
+    Reason(String),
+    Panic(U256),
+    /// An error declared in the `Lock` ABI.
+    Custom {
+        name: String,
+        args: Vec<Token>,
+    },
+    /// `revert()` without data, or running out of gas.
+    Empty,
+    Unknown(Bytes),
+}
+
+impl Revert {
+    pub fn decode(data: &[u8]) -> Self {
+        if data.is_empty() {
+            return Revert::Empty;
+        }
+        let (selector, args) = data.split_at(data.len().min(4));
+        if selector == ERROR_SELECTOR {
+            if let Ok(reason) = String::decode(args) {
+                return Revert::Reason(reason);
+            }
+        }
+        if selector == PANIC_SELECTOR {
+            if let Ok(code) = U256::decode(args) {
+                return Revert::Panic(code);
+            }
+        }
+        for error in LOCK_ABI.errors() {
+            if selector == &error.signature()[..4] {
+                if let Ok(args) = error.decode(args) {
+                    return Revert::Custom {
+                        name: error.name.clone(),
+                        args,
+                    };
+                }
+            }
+        }
+        Revert::Unknown(data.to_vec().into())
+    }
+
+    /// `None` unless the node reported a revert.
+    pub fn from_response(response: &JsonRpcError) -> Option<Self> {
+        let data = response.as_revert_data()?;
+        if data.is_empty() {
+            // Some nodes only put the reason in the message.
+            if let Some(reason) = response.message.strip_prefix("execution reverted: ") {
+                return Some(Revert::Reason(reason.to_string()));
+            }
+        }
+        Some(Self::decode(&data))
+    }
+
+    pub fn from_middleware_error<E: MiddlewareError>(err: &E) -> Option<Self> {
+        Self::from_response(err.as_error_response()?)
+    }
+
+    pub fn from_contract_error<M: Middleware>(err: &ContractError<M>) -> Option<Self> {
+        match err.as_revert() {
+            Some(data) => Some(Self::decode(data)),
+            None => Self::from_middleware_error(err.as_middleware_error()?),
+        }
+    }
+}
+
+impl fmt::Display for Revert {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Revert::Reason(reason) => write!(f, "reverted: {}", reason),
+            Revert::Panic(code) => write!(f, "panicked: {} (0x{:02x})", panic_reason(*code), code),
+            Revert::Custom { name, args } => {
+                let args: Vec<_> = args.iter().map(|arg| arg.to_string()).collect();
+                write!(f, "reverted: {}({})", name, args.join(", "))
+            }
+            Revert::Empty => write!(f, "reverted without a reason"),
+            Revert::Unknown(data) => write!(f, "reverted with undecoded data {}", data),
+        }
+    }
+}
+
+/// Codes of `Panic(uint256)` as documented by Solidity.
+fn panic_reason(code: U256) -> &'static str {
+    match code.low_u64() {
+        0x01 => "assertion failed",
+        0x11 => "arithmetic overflow or underflow",
+        0x12 => "division or modulo by zero",
+        0x21 => "invalid enum value",
+        0x22 => "invalid storage byte array",
+        0x31 => "pop on an empty array",
+        0x32 => "array index out of bounds",
+        0x41 => "out of memory",
+        0x51 => "call to an uninitialized function",
+        _ => "unknown panic",
+    }
+}
+
+/// The decoded revert when there is one, the error itself otherwise.
+pub fn describe_error<E: MiddlewareError>(err: &E) -> String {
+    match Revert::from_middleware_error(err) {
+        Some(revert) => revert.to_string(),
+        None => err.to_string(),
+    }
+}
+
+pub fn describe_contract_error<M: Middleware>(err: &ContractError<M>) -> String {
+    match Revert::from_contract_error(err) {
+        Some(revert) => revert.to_string(),
+        None => err.to_string(),
+    }
+}
+
+/// Finds out why a mined transaction reverted by running it again with
+/// `eth_call` against the state before its block. `None` when the replay
+/// succeeds, i.e. the revert depended on an earlier transaction of the block.
+pub async fn replay(
+    provider: &Provider<Transport>,
+    receipt: &TransactionReceipt,
+) -> anyhow::Result<Option<Revert>> {
+    let hash = receipt.transaction_hash;
+    let tx = provider
+        .get_transaction(hash)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("tx {:?} is unknown to the node", hash))?;
+    let block = receipt
+        .block_number
+        .ok_or_else(|| anyhow::anyhow!("tx {:?} has no block", hash))?;
+    let call: TypedTransaction = TransactionRequest {
+        from: Some(tx.from),
+        to: tx.to.map(Into::into),
+        gas: Some(tx.gas),
+        value: Some(tx.value),
+        data: Some(tx.input),
+        ..Default::default()
+    }
+    .into();
+    let parent = BlockId::Number(BlockNumber::Number(block.saturating_sub(1.into())));
+    match provider.call(&call, Some(parent)).await {
+        Ok(_) => Ok(None),
+        Err(err) => match Revert::from_middleware_error(&err) {
+            Some(revert) => Ok(Some(revert)),
+            None => Err(err.into()),
+        },
+    }
+}
+
+/// [`replay`] rendered for logs and tx records.
+pub async fn describe_receipt(provider: &Provider<Transport>, receipt: &TransactionReceipt) -> String {
+    match replay(provider, receipt).await {
+        Ok(Some(revert)) => revert.to_string(),
+        Ok(None) => "reverted, but not when replayed".to_string(),
+        Err(err) => format!("reverted, replay failed with err: {}", err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers::abi::AbiEncode;
+    use serde_json::json;
+
+    use super::*;
+
+    fn encoded(selector: [u8; 4], args: Vec<u8>) -> Vec<u8> {
+        [selector.to_vec(), args].concat()
+    }
+
+    #[test]
+    fn decodes_reasons() {
+        let data = encoded(
+            ERROR_SELECTOR,
+            "You can't withdraw yet".to_string().encode(),
+        );
+        let revert = Revert::decode(&data);
+        assert_eq!(revert, Revert::Reason("You can't withdraw yet".to_string()));
+        assert_eq!(revert.to_string(), "reverted: You can't withdraw yet");
+    }
+
+    #[test]
+    fn decodes_panics() {
+        let revert = Revert::decode(&encoded(PANIC_SELECTOR, U256::from(0x11).encode()));
+        assert_eq!(revert, Revert::Panic(U256::from(0x11)));
+        assert_eq!(
+            revert.to_string(),
+            "panicked: arithmetic overflow or underflow (0x11)"
+        );
+    }
+
+    #[test]
+    fn keeps_what_it_cannot_decode() {
+        assert_eq!(Revert::decode(&[]), Revert::Empty);
+        for data in [
+            vec![0x08, 0xc3],
+            encoded(ERROR_SELECTOR, vec![0xff; 3]),
+            encoded([0xde, 0xad, 0xbe, 0xef], U256::one().encode()),
+        ] {
+            assert_eq!(Revert::decode(&data), Revert::Unknown(data.clone().into()));
+        }
+    }
+
+    #[test]
+    fn falls_back_to_the_message_without_data() {
+        let response = JsonRpcError {
+            code: 3,
+            message: "execution reverted: not the owner".to_string(),
+            data: Some(json!("0x")),
+        };
+        assert_eq!(
+            Revert::from_response(&response),
+            Some(Revert::Reason("not the owner".to_string()))
+        );
+    }
+}
@@ -0,0 +1,215 @@
+use std::{
+    collections::HashSet,
+    convert::Infallible,
+    fmt, io,
+    path::Path,
+    str::FromStr,
+    sync::{Mutex, OnceLock},
+};
+
+use ethers::{signers::LocalWallet, utils::keccak256};
+use tracing_subscriber::fmt::MakeWriter;
+use zeroize::Zeroizing;
+
+/// A private key, mnemonic or password. Wiped from memory when dropped and
+/// printed as `<redacted>` by `Debug`.
+#[derive(Clone, Default)]
+pub struct SecretString(Zeroizing<String>);
+
+impl SecretString {
+    pub fn new(secret: String) -> Self {
+        Self(Zeroizing::new(secret))
+    }
+
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for SecretString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("<redacted>")
+    }
+}
+
+impl FromStr for SecretString {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self::new(s.to_string()))
+    }
+}
+
+impl From<String> for SecretString {
+    fn from(secret: String) -> Self {
+        Self::new(secret)
+    }
+}
+
+/// Refuses a file holding secrets, e.g. `.env` or a keystore, that anyone
+/// but its owner has any permission on.
+#[cfg(unix)]
+pub fn check_permissions(path: &Path) -> anyhow::Result<()> {
+    use std::os::unix::fs::PermissionsExt as _;
+
+    let mode = std::fs::metadata(path)
+        .map_err(|err| anyhow::anyhow!("reading {}: {}", path.display(), err))?
+        .permissions()
+        .mode();
+    if mode & 0o077 != 0 {
+        anyhow::bail!(
+            "{} is open to other users (mode {:o}), restrict it with `chmod 600 {}`",
+            path.display(),
+            mode & 0o777,
+            path.display()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn check_permissions(_path: &Path) -> anyhow::Result<()> {
+    Ok(())
+}
+
+/// Fingerprints of the keys in use, see [`register_key`].
+fn known_keys() -> &'static Mutex<HashSet<[u8; 32]>> {
+    static KEYS: OnceLock<Mutex<HashSet<[u8; 32]>>> = OnceLock::new();
+    KEYS.get_or_init(Default::default)
+}
+
+/// Has [`ScrubWriter`] scrub the key of `wallet` even where it is written
+/// like a tx hash, with `0x`. Only its keccak hash is kept.
+pub fn register_key(wallet: &LocalWallet) {
+    let key = Zeroizing::new(wallet.signer().to_bytes());
+    known_keys()
+        .lock()
+        .unwrap()
+        .insert(keccak256(key.as_slice()));
+}
+
+/// Wraps the writer of the log output and replaces 32-byte hex strings that
+/// may be private keys with `<redacted>`: bare ones, which ethers never
+/// prints, and `0x` ones that are the key of a registered signer. Tx and
+/// block hashes stay readable.
+#[derive(Debug, Clone)]
+pub struct ScrubWriter<M>(pub M);
+
+impl<'a, M: MakeWriter<'a>> MakeWriter<'a> for ScrubWriter<M> {
+    type Writer = Scrubbed<M::Writer>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        Scrubbed(self.0.make_writer())
+    }
+}
+
+/// A writer of [`ScrubWriter`]. The fmt subscriber hands it one event per
+/// write, so secrets are never split across writes.
+pub struct Scrubbed<W>(W);
+
+impl<W: io::Write> io::Write for Scrubbed<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write_all(&scrub(buf))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+fn scrub(text: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(text.len());
+    let mut start = 0;
+    while start < text.len() {
+        let len = text[start..]
+            .iter()
+            .take_while(|byte| byte.is_ascii_hexdigit())
+            .count();
+        if len == 0 {
+            out.push(text[start]);
+            start += 1;
+            continue;
+        }
+        let run = &text[start..start + len];
+        let prefixed = start >= 2 && text[start - 2..start].eq_ignore_ascii_case(b"0x");
+        if len == 64 && (!prefixed || is_known_key(run)) {
+            out.extend_from_slice(b"<redacted>");
+        } else {
+            out.extend_from_slice(run);
+        }
+        start += len;
+    }
+    out
+}
+
+fn is_known_key(hex_digits: &[u8]) -> bool {
+    let Ok(key) = hex::decode(hex_digits).map(Zeroizing::new) else {
+        return false;
+    };
+    known_keys()
+        .lock()
+        .unwrap()
+        .contains(&keccak256(key.as_slice()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scrubbed(text: &str) -> String {
+        String::from_utf8(scrub(text.as_bytes())).unwrap()
+    }
+
+    #[test]
+    fn redacts_bare_keys() {
+        let key = "ab".repeat(32);
+        assert_eq!(
+            scrubbed(&format!("PRIVATE_KEY={} set", key)),
+            "PRIVATE_KEY=<redacted> set"
+        );
+    }
+
+    #[test]
+    fn keeps_hashes_and_shorter_hex() {
+        let hash = format!("tx 0x{} sent", "cd".repeat(32));
+        assert_eq!(scrubbed(&hash), hash);
+        let address = format!("from 0x{}", "ef".repeat(20));
+        assert_eq!(scrubbed(&address), address);
+        let longer = "12".repeat(40);
+        assert_eq!(scrubbed(&longer), longer);
+    }
+
+    #[test]
+    fn redacts_registered_keys_written_with_0x() {
+        let key = "5a".repeat(32);
+        let wallet: LocalWallet = key.parse().unwrap();
+        register_key(&wallet);
+        assert_eq!(scrubbed(&format!("key 0x{}", key)), "key 0x<redacted>");
+        assert_eq!(
+            scrubbed(&format!("key 0X{}", key.to_uppercase())),
+            "key 0X<redacted>"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn secrets_are_for_the_owner_only() {
+        use std::os::unix::fs::PermissionsExt as _;
+
+        let path = std::env::temp_dir().join(format!("counter-secret-{}-mode", std::process::id()));
+        std::fs::write(&path, "secret").unwrap();
+        let check = |mode| {
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(mode)).unwrap();
+            check_permissions(&path)
+        };
+        let results = [check(0o600), check(0o400), check(0o640), check(0o604)];
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(results[0].is_ok());
+        assert!(results[1].is_ok());
+        let err = results[2].as_ref().unwrap_err().to_string();
+        assert!(err.contains("mode 640"), "{}", err);
+        assert!(results[3].is_err());
+    }
+}
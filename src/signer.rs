@@ -0,0 +1,492 @@
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, bail, Context as _};
+use async_trait::async_trait;
+use ecdsa::SigningKey;
+use ethers::{
+    providers::{Middleware, Provider, ProviderError},
+    signers::{coins_bip39::English, LocalWallet, MnemonicBuilder, Signer, WalletError},
+    types::{
+        transaction::{eip2718::TypedTransaction, eip712::Eip712},
+        Address, Bytes, Signature, SignatureError,
+    },
+    utils::rlp::Rlp,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use zeroize::Zeroizing;
+
+use crate::{
+    config::redact_url,
+    secret::{self, SecretString},
+    transport::Transport,
+    COUNTER_CLIENT,
+};
+
+/// Where the operator key comes from.
+#[derive(Clone)]
+pub enum KeySource {
+    /// Hex, with or without `0x`.
+    PrivateKey(SecretString),
+    /// A Web3 Secret Storage (JSON keystore) file.
+    Keystore {
+        path: PathBuf,
+        password: KeystorePassword,
+    },
+    /// A BIP-39 mnemonic and the full BIP-32 path of the key.
+    Mnemonic {
+        phrase: SecretString,
+        derivation_path: String,
+    },
+    /// A JSON-RPC signer holding the key, see [`RemoteSigner`].
+    Remote {
+        url: String,
+        address: Option<Address>,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub enum KeystorePassword {
+    /// Read from this file, without the trailing newline.
+    File(PathBuf),
+    /// Asked for on the terminal.
+    Prompt,
+}
+
+impl KeySource {
+    /// Loads or connects the signer. Keystores are decrypted here, which
+    /// takes a moment by design.
+    pub async fn signer(&self) -> anyhow::Result<AccountSigner> {
+        tracing::info!(target: COUNTER_CLIENT, "operator key from {:?}", self);
+        let wallet = match self {
+            KeySource::PrivateKey(private_key) => {
+                let hex = private_key.expose();
+                let hex = hex
+                    .strip_prefix("0x")
+                    .or_else(|| hex.strip_prefix("0X"))
+                    .unwrap_or(hex);
+                let bytes = Zeroizing::new(hex::decode(hex)?);
+                LocalWallet::from(SigningKey::from_slice(&bytes)?)
+            }
+            KeySource::Keystore { path, password } => {
+                secret::check_permissions(path)?;
+                let password = password.read(path)?;
+                LocalWallet::decrypt_keystore(path, password.expose())
+                    .with_context(|| format!("decrypting {}", path.display()))?
+            }
+            KeySource::Mnemonic {
+                phrase,
+                derivation_path,
+            } => MnemonicBuilder::<English>::default()
+                .phrase(phrase.expose())
+                .derivation_path(derivation_path)?
+                .build()?,
+            KeySource::Remote { url, address } => {
+                return Ok(AccountSigner::Remote(
+                    RemoteSigner::connect(url, *address).await?,
+                ));
+            }
+        };
+        secret::register_key(&wallet);
+        Ok(AccountSigner::Local(wallet))
+    }
+}
+
+impl KeystorePassword {
+    fn read(&self, keystore: &Path) -> anyhow::Result<SecretString> {
+        match self {
+            KeystorePassword::File(file) => {
+                secret::check_permissions(file)?;
+                let contents = SecretString::new(
+                    fs::read_to_string(file)
+                        .with_context(|| format!("reading {}", file.display()))?,
+                );
+                Ok(SecretString::new(
+                    contents.expose().trim_end_matches(['\r', '\n']).to_string(),
+                ))
+            }
+            KeystorePassword::Prompt => Ok(SecretString::new(rpassword::prompt_password(
+                format!("password of {}: ", keystore.display()),
+            )?)),
+        }
+    }
+}
+
+impl fmt::Debug for KeySource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeySource::PrivateKey(_) => write!(f, "private key <redacted>"),
+            KeySource::Keystore { path, .. } => write!(f, "keystore {}", path.display()),
+            KeySource::Mnemonic {
+                derivation_path, ..
+            } => write!(f, "mnemonic <redacted> at {}", derivation_path),
+            KeySource::Remote { url, address } => {
+                write!(f, "remote signer {}", redact_url(url))?;
+                if let Some(address) = address {
+                    write!(f, " account {:?}", address)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum AccountSignerError {
+    #[error(transparent)]
+    Wallet(#[from] WalletError),
+    #[error("remote signer {method} failed: {source}")]
+    Remote {
+        method: &'static str,
+        #[source]
+        source: ProviderError,
+    },
+    #[error("remote signer {method} returned an invalid answer: {reason}")]
+    Invalid {
+        method: &'static str,
+        reason: String,
+    },
+    #[error("remote signer signature does not match {address:?}: {source}")]
+    Mismatch {
+        address: Address,
+        #[source]
+        source: SignatureError,
+    },
+    #[error("the remote signer does not sign {0}")]
+    Unsupported(&'static str),
+}
+
+/// Signs for one account, with a key in memory or through a remote signer.
+/// Every sender of this crate signs through it.
+#[derive(Debug, Clone)]
+pub enum AccountSigner {
+    Local(LocalWallet),
+    Remote(RemoteSigner),
+}
+
+#[async_trait]
+impl Signer for AccountSigner {
+    type Error = AccountSignerError;
+
+    async fn sign_message<S: Send + Sync + AsRef<[u8]>>(
+        &self,
+        message: S,
+    ) -> Result<Signature, Self::Error> {
+        match self {
+            AccountSigner::Local(wallet) => Ok(wallet.sign_message(message).await?),
+            AccountSigner::Remote(remote) => remote.sign_message(message.as_ref()).await,
+        }
+    }
+
+    async fn sign_transaction(&self, tx: &TypedTransaction) -> Result<Signature, Self::Error> {
+        match self {
+            AccountSigner::Local(wallet) => Ok(wallet.sign_transaction(tx).await?),
+            AccountSigner::Remote(remote) => remote.sign_transaction(tx).await,
+        }
+    }
+
+    async fn sign_typed_data<T: Eip712 + Send + Sync>(
+        &self,
+        payload: &T,
+    ) -> Result<Signature, Self::Error> {
+        match self {
+            AccountSigner::Local(wallet) => Ok(wallet.sign_typed_data(payload).await?),
+            AccountSigner::Remote(_) => Err(AccountSignerError::Unsupported("typed data")),
+        }
+    }
+
+    fn address(&self) -> Address {
+        match self {
+            AccountSigner::Local(wallet) => wallet.address(),
+            AccountSigner::Remote(remote) => remote.address,
+        }
+    }
+
+    fn chain_id(&self) -> u64 {
+        match self {
+            AccountSigner::Local(wallet) => wallet.chain_id(),
+            AccountSigner::Remote(remote) => remote.chain_id,
+        }
+    }
+
+    fn with_chain_id<T: Into<u64>>(self, chain_id: T) -> Self {
+        match self {
+            AccountSigner::Local(wallet) => AccountSigner::Local(wallet.with_chain_id(chain_id)),
+            AccountSigner::Remote(remote) => AccountSigner::Remote(RemoteSigner {
+                chain_id: chain_id.into(),
+                ..remote
+            }),
+        }
+    }
+}
+
+impl From<LocalWallet> for AccountSigner {
+    fn from(wallet: LocalWallet) -> Self {
+        AccountSigner::Local(wallet)
+    }
+}
+
+/// A signer behind JSON-RPC holding the key, e.g. Web3Signer or a node with
+/// unlocked accounts. It is asked for `eth_accounts` when no account is
+/// given, `eth_sign` and `eth_signTransaction`; the latter may answer with
+/// the raw signed tx or an object with a `raw` field. Every signature is
+/// checked to recover to the account before it is used.
+#[derive(Clone)]
+pub struct RemoteSigner {
+    provider: Provider<Transport>,
+    address: Address,
+    chain_id: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+enum SignedTransaction {
+    Raw(Bytes),
+    Object { raw: Bytes },
+}
+
+// The url of the provider may carry credentials.
+impl fmt::Debug for RemoteSigner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RemoteSigner")
+            .field("address", &self.address)
+            .field("chain_id", &self.chain_id)
+            .finish()
+    }
+}
+
+impl RemoteSigner {
+    pub async fn connect(url: &str, address: Option<Address>) -> anyhow::Result<Self> {
+        let transport = Transport::connect(url)
+            .await
+            .with_context(|| format!("connecting remote signer {}", redact_url(url)))?;
+        let provider = Provider::new(transport);
+        let address = match address {
+            Some(address) => address,
+            None => provider
+                .get_accounts()
+                .await
+                .context("remote signer eth_accounts")?
+                .first()
+                .copied()
+                .ok_or_else(|| anyhow!("remote signer {} has no accounts", redact_url(url)))?,
+        };
+        if address.is_zero() {
+            bail!("remote signer account must not be the zero address");
+        }
+        Ok(Self {
+            provider,
+            address,
+            chain_id: 1,
+        })
+    }
+
+    pub fn address(&self) -> Address {
+        self.address
+    }
+
+    async fn sign_message(&self, message: &[u8]) -> Result<Signature, AccountSignerError> {
+        let method = "eth_sign";
+        let signature: Bytes = self
+            .provider
+            .request(method, (self.address, Bytes::from(message.to_vec())))
+            .await
+            .map_err(|source| AccountSignerError::Remote { method, source })?;
+        let signature =
+            Signature::try_from(signature.as_ref()).map_err(|err| AccountSignerError::Invalid {
+                method,
+                reason: err.to_string(),
+            })?;
+        signature
+            .verify(message, self.address)
+            .map_err(|source| AccountSignerError::Mismatch {
+                address: self.address,
+                source,
+            })?;
+        Ok(signature)
+    }
+
+    async fn sign_transaction(
+        &self,
+        tx: &TypedTransaction,
+    ) -> Result<Signature, AccountSignerError> {
+        let method = "eth_signTransaction";
+        let mut tx = tx.clone();
+        tx.set_from(self.address);
+        if tx.chain_id().is_none() {
+            tx.set_chain_id(self.chain_id);
+        }
+        // ethers leaves `chainId` out of tx requests, the signer must not
+        // pick its own.
+        let mut request = json!(tx);
+        if let Some(fields) = request.as_object_mut() {
+            let chain_id = tx.chain_id().unwrap_or_default();
+            fields.insert("chainId".to_string(), json!(chain_id));
+        }
+        let signed: SignedTransaction = self
+            .provider
+            .request(method, [request])
+            .await
+            .map_err(|source| AccountSignerError::Remote { method, source })?;
+        let raw = match signed {
+            SignedTransaction::Raw(raw) | SignedTransaction::Object { raw } => raw,
+        };
+        let (_, signature) = TypedTransaction::decode_signed(&Rlp::new(&raw)).map_err(|err| {
+            AccountSignerError::Invalid {
+                method,
+                reason: err.to_string(),
+            }
+        })?;
+        // A signer that filled in or changed fields signed another tx than
+        // the one that is about to be sent.
+        signature
+            .verify(tx.sighash(), self.address)
+            .map_err(|source| AccountSignerError::Mismatch {
+                address: self.address,
+                source,
+            })?;
+        Ok(signature)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers::{
+        types::{TransactionRequest, H256},
+        utils::hash_message,
+    };
+    use serde_json::Value;
+
+    use super::*;
+    use crate::mock_rpc;
+
+    const KEY: &str = "2222222222222222222222222222222222222222222222222222222222222222";
+
+    fn wallet(key: &str) -> LocalWallet {
+        key.parse().unwrap()
+    }
+
+    /// A remote signer for the account of [`KEY`] that signs with `key` and
+    /// adds `extra_value` wei to every tx it is asked to sign.
+    async fn remote(key: &str, extra_value: u64) -> RemoteSigner {
+        let account = wallet(KEY).address();
+        let signing = wallet(key);
+        let url = mock_rpc::spawn(move |method, params| match method {
+            "eth_accounts" => Ok(json!([account])),
+            "eth_sign" => {
+                let message: Bytes = serde_json::from_value(params[1].clone()).unwrap();
+                let signature = signing.sign_hash(hash_message(message)).unwrap();
+                Ok(json!(Bytes::from(signature.to_vec())))
+            }
+            "eth_signTransaction" => {
+                let mut tx: TransactionRequest = serde_json::from_value(params[0].clone()).unwrap();
+                tx.value = Some(tx.value.unwrap_or_default() + extra_value);
+                let tx = TypedTransaction::Legacy(tx);
+                let signature = signing.sign_transaction_sync(&tx).unwrap();
+                Ok(json!({ "raw": tx.rlp_signed(&signature) }))
+            }
+            _ => Ok(Value::Null),
+        })
+        .await;
+        RemoteSigner::connect(&url, None).await.unwrap()
+    }
+
+    fn tx() -> TypedTransaction {
+        TransactionRequest::new()
+            .to(Address::repeat_byte(0x42))
+            .value(1_000)
+            .nonce(7)
+            .gas(21_000)
+            .gas_price(1_000_000_000)
+            .chain_id(1337)
+            .into()
+    }
+
+    #[tokio::test]
+    async fn remote_signatures_of_the_account_are_accepted() {
+        let signer = remote(KEY, 0).await;
+        assert_eq!(signer.address(), wallet(KEY).address());
+
+        let signature = signer.sign_transaction(&tx()).await.unwrap();
+        let mut expected = tx();
+        expected.set_from(signer.address());
+        assert_eq!(
+            signature,
+            wallet(KEY).sign_transaction_sync(&expected).unwrap()
+        );
+
+        let signature = signer.sign_message(b"counter").await.unwrap();
+        signature.verify("counter", signer.address()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn signatures_of_another_key_are_rejected() {
+        let signer = remote(&"33".repeat(32), 0).await;
+        let err = signer.sign_transaction(&tx()).await.unwrap_err();
+        assert!(matches!(err, AccountSignerError::Mismatch { .. }), "{err}");
+        let err = signer.sign_message(b"counter").await.unwrap_err();
+        assert!(matches!(err, AccountSignerError::Mismatch { .. }), "{err}");
+    }
+
+    #[tokio::test]
+    async fn a_changed_tx_is_rejected() {
+        let signer = remote(KEY, 1).await;
+        let err = signer.sign_transaction(&tx()).await.unwrap_err();
+        assert!(matches!(err, AccountSignerError::Mismatch { .. }), "{err}");
+    }
+
+    /// The key of [`KEY`] encrypted with the password `counter`, with scrypt
+    /// at n = 2 so that it decrypts at once.
+    const KEYSTORE: &str = r#"{
+        "crypto": {
+            "cipher": "aes-128-ctr",
+            "cipherparams": {"iv": "101112131415161718191a1b1c1d1e1f"},
+            "ciphertext": "cb3677414c72c07b4e7e79da665140988592aea23871bc9818513e6d49522d24",
+            "kdf": "scrypt",
+            "kdfparams": {
+                "dklen": 32, "n": 2, "p": 1, "r": 8,
+                "salt": "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f"
+            },
+            "mac": "c611b2cb65c83505c8e6dc9a5f9a92abac5669c1ef848416ca2efd4f46f07ea5"
+        },
+        "id": "3198bc9c-6672-5ab3-d995-4942343ae5b6",
+        "version": 3
+    }"#;
+
+    fn secret_file(name: &str, contents: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("counter-signer-{}-{}", std::process::id(), name));
+        fs::write(&path, contents).unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt as _;
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o600)).unwrap();
+        }
+        path
+    }
+
+    #[tokio::test]
+    async fn keystore_decrypts_to_its_key() {
+        let keystore = secret_file("keystore.json", KEYSTORE);
+        let password = secret_file("password", "counter\n");
+        let source = KeySource::Keystore {
+            path: keystore.clone(),
+            password: KeystorePassword::File(password.clone()),
+        };
+        let signer = source.signer().await;
+        fs::remove_file(keystore).unwrap();
+        fs::remove_file(password).unwrap();
+
+        let AccountSigner::Local(wallet) = signer.unwrap() else {
+            panic!("keystores sign locally");
+        };
+        assert_eq!(
+            H256::from_slice(&wallet.signer().to_bytes()),
+            KEY.parse().unwrap()
+        );
+    }
+}
@@ -0,0 +1,414 @@
+use std::{collections::BTreeMap, fmt, time::Duration};
+
+use anyhow::anyhow;
+use tokio::time::Instant;
+
+use crate::{
+    export::TxRecord,
+    submit_error::SubmitErrorKind,
+    tracker::{SentTx, TxStatus},
+};
+
+const HISTOGRAM_BUCKETS: usize = 10;
+const HISTOGRAM_WIDTH: usize = 40;
+/// Above this many blocks the per-block table is summarized.
+const MAX_BLOCK_ROWS: usize = 30;
+
+/// Sorted latency samples.
+#[derive(Debug, Clone, Default)]
+pub struct Histogram {
+    samples: Vec<Duration>,
+}
+
+impl Histogram {
+    pub fn new(mut samples: Vec<Duration>) -> Self {
+        samples.sort();
+        Self { samples }
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// Nearest-rank percentile, `p` in `0..=100`.
+    pub fn percentile(&self, p: f64) -> Option<Duration> {
+        if self.samples.is_empty() {
+            return None;
+        }
+        let rank = ((p / 100.0) * self.samples.len() as f64).ceil() as usize;
+        Some(self.samples[rank.clamp(1, self.samples.len()) - 1])
+    }
+
+    pub fn min(&self) -> Option<Duration> {
+        self.samples.first().copied()
+    }
+
+    pub fn max(&self) -> Option<Duration> {
+        self.samples.last().copied()
+    }
+
+    pub fn mean(&self) -> Option<Duration> {
+        if self.samples.is_empty() {
+            return None;
+        }
+        Some(self.samples.iter().sum::<Duration>() / self.samples.len() as u32)
+    }
+}
+
+impl fmt::Display for Histogram {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (Some(min), Some(max)) = (self.min(), self.max()) else {
+            return writeln!(f, "    no samples");
+        };
+        writeln!(
+            f,
+            "    n={} min={:?} mean={:?} p50={:?} p90={:?} p99={:?} max={:?}",
+            self.len(),
+            min,
+            self.mean().unwrap_or_default(),
+            self.percentile(50.0).unwrap_or_default(),
+            self.percentile(90.0).unwrap_or_default(),
+            self.percentile(99.0).unwrap_or_default(),
+            max,
+        )?;
+
+        let width = (max - min) / HISTOGRAM_BUCKETS as u32;
+        if width.is_zero() {
+            return Ok(());
+        }
+        let mut counts = [0usize; HISTOGRAM_BUCKETS];
+        for sample in &self.samples {
+            let bucket = ((*sample - min).as_secs_f64() / width.as_secs_f64()) as usize;
+            counts[bucket.min(HISTOGRAM_BUCKETS - 1)] += 1;
+        }
+        let peak = counts.iter().copied().max().unwrap_or(1).max(1);
+        for (i, count) in counts.iter().enumerate() {
+            let lower = min + width * i as u32;
+            let bar = "#".repeat(count * HISTOGRAM_WIDTH / peak);
+            writeln!(f, "    >= {:>12?} | {:<width$} {}", lower, bar, count, width = HISTOGRAM_WIDTH)?;
+        }
+        Ok(())
+    }
+}
+
+/// Latency and throughput summary of a finished run.
+#[derive(Debug, Clone)]
+pub struct RunStats {
+    pub sent: usize,
+    pub statuses: BTreeMap<TxStatus, usize>,
+    /// Submission RPC round-trip.
+    pub submit_latency: Histogram,
+    /// From the start of submission until the receipt was observed.
+    pub inclusion_latency: Histogram,
+    /// From the start of submission until the node announced it pending,
+    /// empty without a pending-transaction subscription.
+    pub mempool_latency: Histogram,
+    pub txs_per_block: BTreeMap<u64, usize>,
+    /// Time spent in the send loop.
+    pub send_window: Duration,
+    /// From the first submission to the last observed inclusion.
+    pub inclusion_window: Option<Duration>,
+    pub submit_errors: BTreeMap<SubmitErrorKind, ErrorCount>,
+}
+
+/// Rejections of one category over a run.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ErrorCount {
+    /// Every rejection, including the ones a retry recovered from.
+    pub seen: usize,
+    /// Txs that ended up failed with it.
+    pub failed: usize,
+}
+
+/// Per-tx inputs of the report, whether observed live or read from an export.
+struct Sample {
+    status: TxStatus,
+    submit_latency: Option<Duration>,
+    inclusion_latency: Option<Duration>,
+    mempool_latency: Option<Duration>,
+    block: Option<u64>,
+    submit_errors: Vec<SubmitErrorKind>,
+    error_kind: Option<SubmitErrorKind>,
+}
+
+impl RunStats {
+    pub fn collect(txs: &[SentTx], send_started: Instant, send_finished: Instant) -> Self {
+        let last_included = txs
+            .iter()
+            .filter(|tx| matches!(tx.status, TxStatus::Mined | TxStatus::Reverted))
+            .filter_map(|tx| tx.included_at)
+            .max();
+        let samples = txs.iter().map(|tx| Sample {
+            status: tx.status,
+            submit_latency: tx.hash.map(|_| tx.submit_latency),
+            inclusion_latency: tx.included_at.map(|at| at - tx.sent_at),
+            mempool_latency: tx.pending_at.map(|at| at.saturating_duration_since(tx.sent_at)),
+            block: tx
+                .receipt
+                .as_ref()
+                .and_then(|r| r.block_number)
+                .map(|n| n.as_u64()),
+            submit_errors: tx.submit_errors.clone(),
+            error_kind: tx.error_kind,
+        });
+        Self::from_samples(
+            samples,
+            send_finished - send_started,
+            last_included.map(|last| last - send_started),
+        )
+    }
+
+    /// Rebuilds the statistics of an exported run; windows are derived from
+    /// the recorded timestamps. Fails on a row whose submit latency is
+    /// negative or not a number.
+    pub fn from_records(records: &[TxRecord]) -> anyhow::Result<Self> {
+        let ms = |ms: u64| Duration::from_millis(ms);
+        let submit_latencies = records
+            .iter()
+            .enumerate()
+            .map(|(row, r)| {
+                Duration::try_from_secs_f64(r.submit_latency_ms / 1000.0).map_err(|_| {
+                    anyhow!(
+                        "row {} (tx {}): invalid submit_latency_ms {}",
+                        row + 1,
+                        r.idx,
+                        r.submit_latency_ms
+                    )
+                })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let first_submit = records.iter().map(|r| r.submitted_at_ms).min();
+        let last_submit = records
+            .iter()
+            .zip(&submit_latencies)
+            .map(|(r, latency)| ms(r.submitted_at_ms) + *latency)
+            .max();
+        let last_included = records
+            .iter()
+            .filter(|r| matches!(r.status, TxStatus::Mined | TxStatus::Reverted))
+            .filter_map(|r| r.included_at_ms)
+            .max();
+
+        let samples = records
+            .iter()
+            .zip(submit_latencies)
+            .map(|(r, latency)| Sample {
+                status: r.status,
+                submit_latency: r.hash.as_ref().map(|_| latency),
+                inclusion_latency: r
+                    .included_at_ms
+                    .map(|at| ms(at.saturating_sub(r.submitted_at_ms))),
+                mempool_latency: r
+                    .pending_at_ms
+                    .map(|at| ms(at.saturating_sub(r.submitted_at_ms))),
+                block: r.block_number,
+                submit_errors: r.submit_errors(),
+                error_kind: r.error_kind,
+            });
+        let send_window = match (first_submit, last_submit) {
+            (Some(first), Some(last)) => last.saturating_sub(ms(first)),
+            _ => Duration::ZERO,
+        };
+        let inclusion_window = match (first_submit, last_included) {
+            (Some(first), Some(last)) => Some(ms(last.saturating_sub(first))),
+            _ => None,
+        };
+        Ok(Self::from_samples(samples, send_window, inclusion_window))
+    }
+
+    fn from_samples(
+        samples: impl Iterator<Item = Sample>,
+        send_window: Duration,
+        inclusion_window: Option<Duration>,
+    ) -> Self {
+        let mut sent = 0;
+        let mut statuses = BTreeMap::new();
+        let mut txs_per_block = BTreeMap::new();
+        let mut submit_latency = vec![];
+        let mut inclusion_latency = vec![];
+        let mut mempool_latency = vec![];
+        let mut submit_errors = BTreeMap::<_, ErrorCount>::new();
+
+        for sample in samples {
+            sent += 1;
+            for kind in sample.submit_errors {
+                submit_errors.entry(kind).or_default().seen += 1;
+            }
+            if let Some(kind) = sample.error_kind {
+                submit_errors.entry(kind).or_default().failed += 1;
+            }
+            *statuses.entry(sample.status).or_insert(0) += 1;
+            submit_latency.extend(sample.submit_latency);
+            mempool_latency.extend(sample.mempool_latency);
+            if !matches!(sample.status, TxStatus::Mined | TxStatus::Reverted) {
+                continue;
+            }
+            inclusion_latency.extend(sample.inclusion_latency);
+            if let Some(block) = sample.block {
+                *txs_per_block.entry(block).or_insert(0) += 1;
+            }
+        }
+
+        Self {
+            sent,
+            statuses,
+            submit_latency: Histogram::new(submit_latency),
+            inclusion_latency: Histogram::new(inclusion_latency),
+            mempool_latency: Histogram::new(mempool_latency),
+            txs_per_block,
+            send_window,
+            inclusion_window,
+            submit_errors,
+        }
+    }
+
+    pub fn count(&self, status: TxStatus) -> usize {
+        self.statuses.get(&status).copied().unwrap_or_default()
+    }
+
+    pub fn included(&self) -> usize {
+        self.count(TxStatus::Mined) + self.count(TxStatus::Reverted)
+    }
+
+    /// Rate at which transactions were handed to the node.
+    pub fn send_tps(&self) -> f64 {
+        rate(self.sent, self.send_window)
+    }
+
+    /// Rate at which transactions made it into blocks.
+    pub fn effective_tps(&self) -> f64 {
+        self.inclusion_window
+            .map(|window| rate(self.included(), window))
+            .unwrap_or_default()
+    }
+}
+
+fn rate(count: usize, window: Duration) -> f64 {
+    if window.is_zero() {
+        0.0
+    } else {
+        count as f64 / window.as_secs_f64()
+    }
+}
+
+impl fmt::Display for RunStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "==== run report ====")?;
+        let statuses: Vec<_> = self
+            .statuses
+            .iter()
+            .map(|(status, n)| format!("{}={}", status, n))
+            .collect();
+        writeln!(f, "txs: sent={} {}", self.sent, statuses.join(" "))?;
+        writeln!(
+            f,
+            "throughput: send {:.2} tps over {:?}, effective {:.2} tps over {:?}",
+            self.send_tps(),
+            self.send_window,
+            self.effective_tps(),
+            self.inclusion_window.unwrap_or_default(),
+        )?;
+        writeln!(f, "submit latency:")?;
+        write!(f, "{}", self.submit_latency)?;
+        writeln!(f, "inclusion latency:")?;
+        write!(f, "{}", self.inclusion_latency)?;
+        if !self.mempool_latency.is_empty() {
+            writeln!(f, "mempool latency:")?;
+            write!(f, "{}", self.mempool_latency)?;
+        }
+        if !self.submit_errors.is_empty() {
+            writeln!(f, "submit errors:")?;
+            for (kind, count) in &self.submit_errors {
+                writeln!(f, "    {:<24} seen={} failed={}", kind, count.seen, count.failed)?;
+            }
+        }
+
+        let counts: Vec<_> = self.txs_per_block.values().copied().collect();
+        match (counts.iter().min(), counts.iter().max()) {
+            (Some(min), Some(max)) => writeln!(
+                f,
+                "txs per block: blocks={} min={} avg={:.2} max={}",
+                counts.len(),
+                min,
+                counts.iter().sum::<usize>() as f64 / counts.len() as f64,
+                max,
+            )?,
+            _ => writeln!(f, "txs per block: no blocks")?,
+        }
+        if self.txs_per_block.len() <= MAX_BLOCK_ROWS {
+            for (block, count) in &self.txs_per_block {
+                writeln!(f, "    block {:>10} | {}", block, count)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn percentile_is_nearest_rank() {
+        let histogram = Histogram::new([7, 3, 10, 1, 5, 9, 2, 8, 4, 6].map(ms).to_vec());
+        assert_eq!(histogram.percentile(0.0), Some(ms(1)));
+        assert_eq!(histogram.percentile(10.0), Some(ms(1)));
+        assert_eq!(histogram.percentile(11.0), Some(ms(2)));
+        assert_eq!(histogram.percentile(50.0), Some(ms(5)));
+        assert_eq!(histogram.percentile(90.0), Some(ms(9)));
+        assert_eq!(histogram.percentile(99.0), Some(ms(10)));
+        assert_eq!(histogram.percentile(100.0), Some(ms(10)));
+        assert_eq!(Histogram::new(vec![ms(4)]).percentile(50.0), Some(ms(4)));
+        assert_eq!(Histogram::default().percentile(50.0), None);
+    }
+
+    fn record(idx: u64, submit_latency_ms: f64) -> TxRecord {
+        TxRecord {
+            idx,
+            from: format!("0x{:040x}", 1),
+            hash: Some(format!("0x{:064x}", idx)),
+            nonce: Some(idx),
+            gas_used: Some(21_000),
+            effective_gas_price: Some(1),
+            block_number: Some(10),
+            submitted_at_ms: 1_000 + idx,
+            submit_latency_ms,
+            included_at_ms: Some(3_000),
+            pending_at_ms: None,
+            status: TxStatus::Mined,
+            error: None,
+            error_kind: None,
+            submit_errors: None,
+        }
+    }
+
+    #[test]
+    fn rebuilds_exported_runs() {
+        let stats = RunStats::from_records(&[record(0, 20.0), record(1, 30.5)]).unwrap();
+        assert_eq!(stats.submit_latency.len(), 2);
+        assert_eq!(
+            stats.submit_latency.max(),
+            Some(Duration::from_micros(30_500))
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_submit_latencies() {
+        for latency in [-1.0, f64::NAN, f64::INFINITY] {
+            let err = RunStats::from_records(&[record(0, 20.0), record(7, latency)]).unwrap_err();
+            assert!(
+                err.to_string().starts_with("row 2 (tx 7): invalid submit_latency_ms"),
+                "{}",
+                err
+            );
+        }
+    }
+}
@@ -0,0 +1,383 @@
+use std::{collections::BTreeMap, fmt, str::FromStr, time::Duration};
+
+use anyhow::bail;
+use ethers::providers::MiddlewareError;
+use serde::{Deserialize, Serialize};
+
+use crate::revert;
+
+/// Why the node refused a transaction, classified from the RPC error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SubmitErrorKind {
+    /// The nonce was already used, by an earlier tx or one sent elsewhere.
+    NonceTooLow,
+    /// The nonce leaves a gap the node won't queue.
+    NonceTooHigh,
+    /// Another tx holds the nonce and the fees are not far enough above its
+    /// own to replace it.
+    ReplacementUnderpriced,
+    /// The fees are below the pool minimum or the base fee, the nonce is
+    /// still free.
+    FeeTooLow,
+    InsufficientFunds,
+    IntrinsicGasTooLow,
+    TxPoolFull,
+    RateLimited,
+    /// The request timed out, the node may or may not have the tx.
+    Timeout,
+    Internal,
+    /// The node already has this exact tx, e.g. from a send that timed out.
+    /// Counted as submitted rather than rejected.
+    AlreadyKnown,
+    Other,
+}
+
+impl SubmitErrorKind {
+    pub const ALL: [SubmitErrorKind; 12] = [
+        SubmitErrorKind::NonceTooLow,
+        SubmitErrorKind::NonceTooHigh,
+        SubmitErrorKind::ReplacementUnderpriced,
+        SubmitErrorKind::FeeTooLow,
+        SubmitErrorKind::InsufficientFunds,
+        SubmitErrorKind::IntrinsicGasTooLow,
+        SubmitErrorKind::TxPoolFull,
+        SubmitErrorKind::RateLimited,
+        SubmitErrorKind::Timeout,
+        SubmitErrorKind::Internal,
+        SubmitErrorKind::AlreadyKnown,
+        SubmitErrorKind::Other,
+    ];
+
+    /// Error messages differ between clients, these cover geth, erigon,
+    /// nethermind, besu and the usual hosted providers.
+    pub fn classify(code: Option<i64>, message: &str) -> Self {
+        let message = message.to_ascii_lowercase();
+        let has = |needles: &[&str]| needles.iter().any(|needle| message.contains(needle));
+        if has(&[
+            "already known",
+            "alreadyknown",
+            "already imported",
+            "known transaction",
+        ]) {
+            SubmitErrorKind::AlreadyKnown
+        } else if has(&[
+            "nonce too low",
+            "nonce is too low",
+            "old nonce",
+            "oldnonce",
+            "nonce has already been used",
+        ]) {
+            SubmitErrorKind::NonceTooLow
+        } else if has(&["nonce too high", "nonce is too high", "nonce gap"]) {
+            SubmitErrorKind::NonceTooHigh
+        } else if has(&[
+            "replacement transaction underpriced",
+            "replacement_underpriced",
+            "replacement fee too low",
+        ]) {
+            SubmitErrorKind::ReplacementUnderpriced
+        } else if has(&[
+            "underpriced",
+            "fee too low",
+            "feetoolow",
+            "max fee per gas less than block base fee",
+            "gas price too low",
+            "gas_price_too_low",
+        ]) {
+            SubmitErrorKind::FeeTooLow
+        } else if has(&["insufficient funds", "insufficient balance"]) {
+            SubmitErrorKind::InsufficientFunds
+        } else if has(&["intrinsic gas too low", "gas too low"]) {
+            SubmitErrorKind::IntrinsicGasTooLow
+        } else if has(&[
+            "txpool is full",
+            "pool is full",
+            "txpool full",
+            "too many transactions",
+        ]) {
+            SubmitErrorKind::TxPoolFull
+        } else if code == Some(429) || has(&["rate limit", "too many requests", "request limit"]) {
+            SubmitErrorKind::RateLimited
+        } else if has(&["timed out", "timeout", "deadline"]) {
+            SubmitErrorKind::Timeout
+        } else if code == Some(-32603) || has(&["internal error"]) {
+            SubmitErrorKind::Internal
+        } else {
+            SubmitErrorKind::Other
+        }
+    }
+
+    /// Whether the nonce of the rejected tx can be handed out again. A tx
+    /// that timed out or is already known may still land, and a nonce too
+    /// low or held by an underpriced replacement is taken.
+    pub fn frees_nonce(self) -> bool {
+        !matches!(
+            self,
+            SubmitErrorKind::NonceTooLow
+                | SubmitErrorKind::ReplacementUnderpriced
+                | SubmitErrorKind::Timeout
+                | SubmitErrorKind::AlreadyKnown
+        )
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            SubmitErrorKind::NonceTooLow => "nonce_too_low",
+            SubmitErrorKind::NonceTooHigh => "nonce_too_high",
+            SubmitErrorKind::ReplacementUnderpriced => "replacement_underpriced",
+            SubmitErrorKind::FeeTooLow => "fee_too_low",
+            SubmitErrorKind::InsufficientFunds => "insufficient_funds",
+            SubmitErrorKind::IntrinsicGasTooLow => "intrinsic_gas_too_low",
+            SubmitErrorKind::TxPoolFull => "txpool_full",
+            SubmitErrorKind::RateLimited => "rate_limited",
+            SubmitErrorKind::Timeout => "timeout",
+            SubmitErrorKind::Internal => "internal",
+            SubmitErrorKind::AlreadyKnown => "already_known",
+            SubmitErrorKind::Other => "other",
+        }
+    }
+}
+
+impl fmt::Display for SubmitErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(self.name())
+    }
+}
+
+impl FromStr for SubmitErrorKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim().to_ascii_lowercase().replace('-', "_");
+        match Self::ALL.into_iter().find(|kind| kind.name() == s) {
+            Some(kind) => Ok(kind),
+            None => bail!("unknown submit error `{}`", s),
+        }
+    }
+}
+
+/// A rejected submission with its category.
+#[derive(Debug, Clone, thiserror::Error)]
+#[error("{kind}: {message}")]
+pub struct SubmitError {
+    pub kind: SubmitErrorKind,
+    pub message: String,
+}
+
+impl SubmitError {
+    pub fn classify<E: MiddlewareError>(err: &E) -> Self {
+        let message = revert::describe_error(err);
+        let kind = match err.as_error_response() {
+            Some(response) => SubmitErrorKind::classify(Some(response.code), &response.message),
+            // No JSON-RPC error, the transport failed; HTTP errors carry
+            // their status in the message.
+            None => SubmitErrorKind::classify(None, &err.to_string()),
+        };
+        Self { kind, message }
+    }
+}
+
+/// What the workload does after a rejected submission.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Recovery {
+    /// Reload the pending nonce and retry with the next free one.
+    ResyncNonce,
+    /// Retry with fees raised by the configured bump.
+    BumpFee,
+    /// Retry the same tx after an exponential backoff.
+    BackOff,
+    /// Stop sending, the rest of the run would fail the same way.
+    Abort,
+    /// Record the tx as failed and move on.
+    Fail,
+}
+
+impl fmt::Display for Recovery {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Recovery::ResyncNonce => "resync",
+            Recovery::BumpFee => "bump-fee",
+            Recovery::BackOff => "backoff",
+            Recovery::Abort => "abort",
+            Recovery::Fail => "fail",
+        };
+        f.write_str(s)
+    }
+}
+
+impl FromStr for Recovery {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "resync" | "resync-nonce" => Ok(Recovery::ResyncNonce),
+            "bump-fee" | "bump" => Ok(Recovery::BumpFee),
+            "backoff" | "back-off" => Ok(Recovery::BackOff),
+            "abort" => Ok(Recovery::Abort),
+            "fail" => Ok(Recovery::Fail),
+            _ => bail!(
+                "unknown recovery `{}`, expected `resync`, `bump-fee`, `backoff`, `abort` or `fail`",
+                s
+            ),
+        }
+    }
+}
+
+/// `<error>=<recovery>`, e.g. `txpool_full=abort`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PolicyOverride(pub SubmitErrorKind, pub Recovery);
+
+impl FromStr for PolicyOverride {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((kind, recovery)) = s.split_once('=') else {
+            bail!("expected `<error>=<recovery>`, got `{}`", s);
+        };
+        Ok(PolicyOverride(kind.parse()?, recovery.parse()?))
+    }
+}
+
+/// Recovery per error category, and the bounds on retrying.
+#[derive(Debug, Clone)]
+pub struct RecoveryPolicy {
+    pub policies: BTreeMap<SubmitErrorKind, Recovery>,
+    /// Retries of one tx across all recoveries.
+    pub max_retries: u32,
+    /// First backoff, doubled on every further retry.
+    pub backoff: Duration,
+    pub fee_bump_percent: u64,
+}
+
+impl Default for RecoveryPolicy {
+    fn default() -> Self {
+        use Recovery::*;
+        use SubmitErrorKind::*;
+
+        Self {
+            policies: BTreeMap::from([
+                (NonceTooLow, ResyncNonce),
+                (NonceTooHigh, ResyncNonce),
+                (ReplacementUnderpriced, BumpFee),
+                (FeeTooLow, BumpFee),
+                (InsufficientFunds, Abort),
+                (IntrinsicGasTooLow, Abort),
+                (TxPoolFull, BackOff),
+                (RateLimited, BackOff),
+                // The identical tx is sent again: a node that got the first
+                // one answers `already known`, which counts as submitted.
+                // A new nonce would leave the first one untracked.
+                (Timeout, BackOff),
+                (Internal, BackOff),
+                (Other, Fail),
+            ]),
+            max_retries: 3,
+            backoff: Duration::from_millis(500),
+            fee_bump_percent: 15,
+        }
+    }
+}
+
+impl RecoveryPolicy {
+    pub fn with_overrides(mut self, overrides: &[PolicyOverride]) -> Self {
+        for PolicyOverride(kind, recovery) in overrides {
+            self.policies.insert(*kind, *recovery);
+        }
+        self
+    }
+
+    pub fn recovery(&self, kind: SubmitErrorKind) -> Recovery {
+        self.policies.get(&kind).copied().unwrap_or(Recovery::Fail)
+    }
+
+    /// Backoff before retry number `retry`, counting from zero.
+    pub fn backoff(&self, retry: u32) -> Duration {
+        self.backoff * 2u32.saturating_pow(retry.min(16))
+    }
+}
+
+impl fmt::Display for RecoveryPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let policies: Vec<_> = self
+            .policies
+            .iter()
+            .map(|(kind, recovery)| format!("{}={}", kind, recovery))
+            .collect();
+        write!(
+            f,
+            "{} retries: {} backoff: {:?} fee bump: {}%",
+            policies.join(","),
+            self.max_retries,
+            self.backoff,
+            self.fee_bump_percent
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use SubmitErrorKind::*;
+
+    #[test]
+    fn classifies_client_messages() {
+        let cases = [
+            (None, "nonce too low", NonceTooLow),
+            (None, "Nonce too low: next nonce 5, tx nonce 3", NonceTooLow),
+            (None, "OldNonce", NonceTooLow),
+            (None, "nonce too high", NonceTooHigh),
+            (None, "replacement transaction underpriced", ReplacementUnderpriced),
+            (None, "REPLACEMENT_UNDERPRICED", ReplacementUnderpriced),
+            (None, "replacement fee too low", ReplacementUnderpriced),
+            (None, "transaction underpriced", FeeTooLow),
+            (None, "max fee per gas less than block base fee", FeeTooLow),
+            (None, "FeeTooLow, max fee per gas 1 is below the minimum", FeeTooLow),
+            (None, "GAS_PRICE_TOO_LOW", FeeTooLow),
+            (None, "insufficient funds for gas * price + value", InsufficientFunds),
+            (None, "intrinsic gas too low", IntrinsicGasTooLow),
+            (None, "txpool is full", TxPoolFull),
+            (Some(429), "whatever", RateLimited),
+            (None, "HTTP status client error (429 Too Many Requests)", RateLimited),
+            (None, "daily request limit reached", RateLimited),
+            (None, "request timed out", Timeout),
+            (Some(-32603), "oops", Internal),
+            (None, "already known", AlreadyKnown),
+            (Some(-32010), "AlreadyKnown", AlreadyKnown),
+            (None, "Transaction already imported", AlreadyKnown),
+            (None, "known transaction: 0x1429aa", AlreadyKnown),
+            (None, "execution reverted", Other),
+        ];
+        for (code, message, kind) in cases {
+            assert_eq!(SubmitErrorKind::classify(code, message), kind, "{}", message);
+        }
+    }
+
+    #[test]
+    fn only_taken_nonces_are_kept() {
+        assert!(FeeTooLow.frees_nonce());
+        assert!(NonceTooHigh.frees_nonce());
+        assert!(!ReplacementUnderpriced.frees_nonce());
+        assert!(!Timeout.frees_nonce());
+        assert!(!AlreadyKnown.frees_nonce());
+    }
+
+    #[test]
+    fn numbers_containing_429_are_not_rate_limits() {
+        for message in [
+            "invalid sender 0x4290000000000000000000000000000000000000",
+            "invalid chain id 1429",
+            "exceeds block gas limit 30000429",
+        ] {
+            assert_eq!(SubmitErrorKind::classify(Some(-32000), message), Other, "{}", message);
+        }
+    }
+
+    #[test]
+    fn parses_policy_overrides() {
+        let PolicyOverride(kind, recovery) = "txpool-full=abort".parse().unwrap();
+        assert_eq!((kind, recovery), (TxPoolFull, Recovery::Abort));
+        assert!("txpool_full".parse::<PolicyOverride>().is_err());
+        assert!("bogus=abort".parse::<PolicyOverride>().is_err());
+    }
+}
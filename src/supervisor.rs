@@ -0,0 +1,520 @@
+use std::{
+    collections::BTreeMap,
+    fmt,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use ethers::{
+    providers::Middleware,
+    types::{transaction::eip2718::TypedTransaction, BlockNumber, TxHash, U256},
+};
+use tokio::{task::JoinHandle, time::Instant};
+
+use crate::{
+    fees::FeeOracle,
+    nonce::{NonceAllocator, Slot},
+    replace::{self, ReplaceAction},
+    submit_error::{SubmitError, SubmitErrorKind},
+    tracker::Replacements,
+    COUNTER_CLIENT,
+};
+
+#[derive(Debug, Clone)]
+pub struct SupervisorConfig {
+    /// How often every account's nonces are compared with the node's.
+    pub interval: Duration,
+    /// How long the mined nonce may stand still with txs pending before the
+    /// lowest one is sped up.
+    pub stuck_after: Duration,
+    /// Replace every tx still pending this long after it was sent.
+    pub inclusion_deadline: Option<Duration>,
+    pub on_deadline: ReplaceAction,
+    /// Replacements of one nonce before the supervisor leaves it alone.
+    pub max_replacements: u32,
+    pub fee_bump_percent: u64,
+}
+
+/// What the supervisor had to repair over a run.
+#[derive(Debug, Clone, Default)]
+pub struct SupervisorReport {
+    /// Nonces nothing was sent for, filled with a zero-value self-transfer.
+    pub gaps_filled: usize,
+    /// Txs the node lost, sent again unchanged.
+    pub rebroadcast: usize,
+    /// Stuck txs replaced with bumped fees.
+    pub sped_up: usize,
+    /// Stuck txs replaced with a zero-value self-transfer.
+    pub cancelled: usize,
+    pub failed_repairs: usize,
+}
+
+impl fmt::Display for SupervisorReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "==== nonce supervisor ====")?;
+        writeln!(
+            f,
+            "gaps filled: {} rebroadcast: {} sped up: {} cancelled: {} failed repairs: {}",
+            self.gaps_filled, self.rebroadcast, self.sped_up, self.cancelled, self.failed_repairs
+        )
+    }
+}
+
+/// Where one account stood at the last check.
+struct Watch {
+    allocator: Arc<NonceAllocator>,
+    latest: U256,
+    since: Instant,
+    /// Replacements per nonce not yet mined.
+    replaced: BTreeMap<U256, u32>,
+}
+
+/// Compares the nonces handed out to each account with the node's latest
+/// and pending counts, and repairs what keeps later txs from being mined:
+/// gaps left by rejected or lost submissions, and pending txs that do not
+/// get mined.
+pub struct NonceSupervisor {
+    report: Arc<Mutex<SupervisorReport>>,
+    task: JoinHandle<()>,
+}
+
+impl NonceSupervisor {
+    pub fn spawn(
+        accounts: Vec<Arc<NonceAllocator>>,
+        fees: Arc<FeeOracle>,
+        replacements: Replacements,
+        config: SupervisorConfig,
+    ) -> Self {
+        let report = Arc::new(Mutex::new(SupervisorReport::default()));
+        let supervisor = Supervisor {
+            fees,
+            replacements,
+            config,
+            report: report.clone(),
+        };
+        let watches = accounts
+            .into_iter()
+            .map(|allocator| Watch {
+                allocator,
+                latest: U256::zero(),
+                since: Instant::now(),
+                replaced: BTreeMap::new(),
+            })
+            .collect();
+        let task = tokio::spawn(supervisor.run(watches));
+        Self { report, task }
+    }
+
+    pub fn report(&self) -> SupervisorReport {
+        self.report.lock().unwrap().clone()
+    }
+}
+
+impl Drop for NonceSupervisor {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+struct Supervisor {
+    fees: Arc<FeeOracle>,
+    replacements: Replacements,
+    config: SupervisorConfig,
+    report: Arc<Mutex<SupervisorReport>>,
+}
+
+impl Supervisor {
+    async fn run(self, mut watches: Vec<Watch>) {
+        loop {
+            tokio::time::sleep(self.config.interval).await;
+            for watch in &mut watches {
+                if let Err(err) = self.check(watch).await {
+                    tracing::warn!(target: COUNTER_CLIENT, "nonce check failed with err: {}", err);
+                }
+            }
+        }
+    }
+
+    async fn check(&self, watch: &mut Watch) -> anyhow::Result<()> {
+        let allocator = watch.allocator.clone();
+        let latest = allocator.count(BlockNumber::Latest).await?;
+        let pending = allocator.count(BlockNumber::Pending).await?;
+        allocator.mined(latest);
+        watch.replaced = watch.replaced.split_off(&latest);
+        if latest != watch.latest {
+            watch.latest = latest;
+            watch.since = Instant::now();
+        }
+
+        // The node's pending count stops at the first nonce it has no tx
+        // for, everything handed out above it waits behind that gap.
+        let mut nonce = pending;
+        while nonce < allocator.peek() {
+            match allocator.slot(nonce) {
+                // Still being submitted.
+                Some(Slot::InFlight) => {}
+                Some(Slot::Sent { tx, hash, at }) => {
+                    if nonce == pending && at.elapsed() >= self.config.interval {
+                        self.resend(&allocator, nonce, *tx, hash, None).await;
+                    }
+                }
+                None => {
+                    if allocator.claim(nonce) {
+                        self.fill(&allocator, nonce).await;
+                    }
+                }
+            }
+            nonce += U256::one();
+        }
+
+        if pending > latest && watch.since.elapsed() >= self.config.stuck_after {
+            match allocator.slot(latest) {
+                Some(Slot::Sent { tx, hash, at }) => {
+                    if at.elapsed() >= self.config.stuck_after {
+                        self.replace(watch, latest, *tx, hash, ReplaceAction::SpeedUp)
+                            .await;
+                    }
+                }
+                _ => tracing::warn!(
+                    target: COUNTER_CLIENT,
+                    "from:{:?} nonce {} pending for {:?} but not sent by this run",
+                    allocator.client().inner().address(),
+                    latest,
+                    watch.since.elapsed()
+                ),
+            }
+            // Give the repair time to land before the next one.
+            watch.since = Instant::now();
+        }
+
+        // Replacing resets the sent time, a tx just sped up above waits for
+        // another deadline.
+        if let Some(deadline) = self.config.inclusion_deadline {
+            let mut nonce = latest;
+            while nonce < pending.min(allocator.peek()) {
+                if let Some(Slot::Sent { tx, hash, at }) = allocator.slot(nonce) {
+                    if at.elapsed() >= deadline {
+                        self.replace(watch, nonce, *tx, hash, self.config.on_deadline)
+                            .await;
+                    }
+                }
+                nonce += U256::one();
+            }
+        }
+        Ok(())
+    }
+
+    /// Sends a zero-value self-transfer at `nonce`, claimed beforehand.
+    async fn fill(&self, allocator: &NonceAllocator, nonce: U256) {
+        let from = allocator.client().inner().address();
+        let mut tx = replace::self_transfer(from, nonce);
+        self.fees.current().apply(&mut tx);
+        match allocator
+            .client()
+            .inner()
+            .send_transaction(tx.clone(), None)
+            .await
+        {
+            Ok(pending) => {
+                let hash = pending.tx_hash();
+                tracing::warn!(target: COUNTER_CLIENT, "from:{:?} filled nonce gap {} with {:?}", from, nonce, hash);
+                allocator.sent(nonce, tx, hash);
+                self.report.lock().unwrap().gaps_filled += 1;
+            }
+            Err(err) => {
+                let err = SubmitError::classify(&err);
+                tracing::warn!(target: COUNTER_CLIENT, "from:{:?} filling nonce gap {} failed with err: {}", from, nonce, err);
+                if err.kind.frees_nonce() {
+                    allocator.release(nonce);
+                } else {
+                    allocator.forget(nonce);
+                }
+                self.report.lock().unwrap().failed_repairs += 1;
+            }
+        }
+    }
+
+    /// Replaces the tx at `nonce`, up to the configured number of times.
+    async fn replace(
+        &self,
+        watch: &mut Watch,
+        nonce: U256,
+        tx: TypedTransaction,
+        hash: TxHash,
+        action: ReplaceAction,
+    ) {
+        let replaced = watch.replaced.entry(nonce).or_default();
+        if *replaced >= self.config.max_replacements {
+            if *replaced == self.config.max_replacements {
+                tracing::warn!(
+                    target: COUNTER_CLIENT,
+                    "from:{:?} nonce {} still pending after {} replacements, leaving it",
+                    watch.allocator.client().inner().address(),
+                    nonce,
+                    replaced
+                );
+                *replaced += 1;
+            }
+            return;
+        }
+        *replaced += 1;
+        self.resend(&watch.allocator, nonce, tx, hash, Some(action))
+            .await;
+    }
+
+    /// Sends `tx` again at `nonce`, unchanged without an action.
+    async fn resend(
+        &self,
+        allocator: &NonceAllocator,
+        nonce: U256,
+        mut tx: TypedTransaction,
+        replaced: TxHash,
+        action: Option<ReplaceAction>,
+    ) {
+        let from = allocator.client().inner().address();
+        if let Some(action) = action {
+            let current = self.fees.current();
+            let bump = self.config.fee_bump_percent;
+            tx = replace::replacement(from, nonce, &tx, action, current, bump);
+        }
+        match allocator
+            .client()
+            .inner()
+            .send_transaction(tx.clone(), None)
+            .await
+        {
+            Ok(pending) => {
+                let hash = pending.tx_hash();
+                tracing::warn!(
+                    target: COUNTER_CLIENT,
+                    "from:{:?} re-sent nonce {} as {:?}{}",
+                    from,
+                    nonce,
+                    hash,
+                    action
+                        .map(|action| format!(", {}", action))
+                        .unwrap_or_default()
+                );
+                allocator.sent(nonce, tx, hash);
+                let mut report = self.report.lock().unwrap();
+                match action {
+                    None => report.rebroadcast += 1,
+                    Some(ReplaceAction::SpeedUp) => {
+                        // Still the same call, the tracker follows it instead.
+                        self.replacements.record(replaced, hash);
+                        report.sped_up += 1;
+                    }
+                    // The tracker sees the nonce taken and the tx replaced.
+                    Some(ReplaceAction::Cancel) => report.cancelled += 1,
+                }
+            }
+            Err(err) => {
+                let err = SubmitError::classify(&err);
+                tracing::warn!(target: COUNTER_CLIENT, "from:{:?} re-sending nonce {} failed with err: {}", from, nonce, err);
+                // Mined in the meantime, nothing left to repair.
+                if err.kind == SubmitErrorKind::NonceTooLow {
+                    allocator.forget(nonce);
+                } else if action.is_none() && err.kind == SubmitErrorKind::AlreadyKnown {
+                    // The node still has it after all.
+                    allocator.sent(nonce, tx, replaced);
+                } else {
+                    self.report.lock().unwrap().failed_repairs += 1;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers::{
+        signers::LocalWallet,
+        types::{Bytes, NameOrAddress},
+        utils::{keccak256, rlp::Rlp},
+    };
+    use serde_json::json;
+
+    use super::*;
+    use crate::{
+        chain::ChainCapabilities,
+        fees::{FeeConfig, FeeStrategy, GasPolicy},
+        get_wallet_signer_provider, mock_rpc,
+    };
+
+    const CHAIN_ID: u64 = 1337;
+
+    /// The account's nonce counts and the raw txs it was sent.
+    #[derive(Default)]
+    struct Node {
+        latest: u64,
+        pending: u64,
+        sent: Vec<Bytes>,
+    }
+
+    impl Node {
+        fn sent_txs(&self) -> Vec<(TypedTransaction, TxHash)> {
+            self.sent
+                .iter()
+                .map(|raw| {
+                    let (tx, _) = TypedTransaction::decode_signed(&Rlp::new(raw)).unwrap();
+                    (tx, TxHash::from(keccak256(raw)))
+                })
+                .collect()
+        }
+    }
+
+    /// A supervisor of one account on `node` whose next nonce is 5.
+    async fn supervise(node: Arc<Mutex<Node>>, max_replacements: u32) -> (Supervisor, Watch) {
+        let counts = node.clone();
+        let provider = mock_rpc::provider(move |method, params| {
+            let mut node = counts.lock().unwrap();
+            match method {
+                "eth_getTransactionCount" => match params[1].as_str() {
+                    Some("latest") => Ok(json!(U256::from(node.latest))),
+                    _ => Ok(json!(U256::from(node.pending))),
+                },
+                "eth_sendRawTransaction" => {
+                    let raw: Bytes = serde_json::from_value(params[0].clone()).unwrap();
+                    let hash = TxHash::from(keccak256(&raw));
+                    node.sent.push(raw);
+                    Ok(json!(hash))
+                }
+                _ => Err(format!("unexpected {}", method)),
+            }
+        })
+        .await;
+        let wallet: LocalWallet = "11".repeat(32).parse().unwrap();
+        let account = Arc::new(get_wallet_signer_provider(
+            provider.clone(),
+            CHAIN_ID,
+            wallet,
+        ));
+        let fees = FeeOracle::new(
+            provider,
+            ChainCapabilities {
+                chain_id: CHAIN_ID,
+                london: false,
+                max_priority_fee: false,
+            },
+            FeeConfig {
+                strategy: FeeStrategy::Legacy {
+                    gas_price: Some(1_000_000_000.into()),
+                },
+                base_fee_multiplier: 1.0,
+                refresh_interval: Duration::from_secs(60),
+                gas: GasPolicy::Fixed(100_000),
+            },
+        )
+        .await
+        .unwrap();
+        {
+            let mut node = node.lock().unwrap();
+            node.latest = 5;
+            node.pending = 5;
+        }
+        let allocator = Arc::new(NonceAllocator::new(account));
+        allocator.init().await.unwrap();
+        let supervisor = Supervisor {
+            fees,
+            replacements: Replacements::default(),
+            config: SupervisorConfig {
+                interval: Duration::ZERO,
+                stuck_after: Duration::ZERO,
+                inclusion_deadline: None,
+                on_deadline: ReplaceAction::Cancel,
+                max_replacements,
+                fee_bump_percent: 10,
+            },
+            report: Default::default(),
+        };
+        let watch = Watch {
+            allocator,
+            latest: U256::zero(),
+            since: Instant::now(),
+            replaced: BTreeMap::new(),
+        };
+        (supervisor, watch)
+    }
+
+    /// Hands out the next nonce and sends a transfer at it through the node.
+    async fn send(allocator: &NonceAllocator) -> (U256, TxHash) {
+        let nonce = allocator.next();
+        let from = allocator.client().inner().address();
+        let mut tx = replace::self_transfer(from, nonce);
+        tx.set_value(1).set_gas_price(1_000_000_000);
+        let hash = allocator
+            .client()
+            .inner()
+            .send_transaction(tx.clone(), None)
+            .await
+            .unwrap()
+            .tx_hash();
+        allocator.sent(nonce, tx, hash);
+        (nonce, hash)
+    }
+
+    #[tokio::test]
+    async fn nonce_gaps_are_filled_with_a_self_transfer() {
+        let node = Arc::new(Mutex::new(Node::default()));
+        let (supervisor, mut watch) = supervise(node.clone(), 2).await;
+        let allocator = watch.allocator.clone();
+        // 5 was rejected while 6 was sent, which waits behind it.
+        let gap = allocator.next();
+        let (queued, _) = send(&allocator).await;
+        allocator.release(gap);
+        assert_eq!(queued, gap + 1);
+
+        supervisor.check(&mut watch).await.unwrap();
+
+        let sent = node.lock().unwrap().sent_txs();
+        assert_eq!(sent.len(), 2);
+        let (fill, hash) = &sent[1];
+        let from = allocator.client().inner().address();
+        assert_eq!(fill.nonce(), Some(&gap));
+        assert_eq!(fill.to(), Some(&NameOrAddress::Address(from)));
+        assert_eq!(fill.value(), Some(&U256::zero()));
+        assert!(
+            matches!(allocator.slot(gap), Some(Slot::Sent { hash: sent, .. }) if sent == *hash)
+        );
+        assert_eq!(supervisor.report.lock().unwrap().gaps_filled, 1);
+    }
+
+    #[tokio::test]
+    async fn txs_missing_from_the_pool_are_sent_again_unchanged() {
+        let node = Arc::new(Mutex::new(Node::default()));
+        let (supervisor, mut watch) = supervise(node.clone(), 2).await;
+        // The node's pending count stays at 5, it lost the tx.
+        let (nonce, hash) = send(&watch.allocator).await;
+
+        supervisor.check(&mut watch).await.unwrap();
+
+        let node = node.lock().unwrap();
+        assert_eq!(node.sent.len(), 2);
+        assert_eq!(node.sent[0], node.sent[1]);
+        assert!(
+            matches!(watch.allocator.slot(nonce), Some(Slot::Sent { hash: sent, .. }) if sent == hash)
+        );
+        assert_eq!(supervisor.report.lock().unwrap().rebroadcast, 1);
+    }
+
+    #[tokio::test]
+    async fn stuck_txs_are_replaced_at_most_max_replacements_times() {
+        let node = Arc::new(Mutex::new(Node::default()));
+        let (supervisor, mut watch) = supervise(node.clone(), 2).await;
+        let (nonce, original) = send(&watch.allocator).await;
+        // In the pool, never mined.
+        node.lock().unwrap().pending = 6;
+
+        for _ in 0..4 {
+            supervisor.check(&mut watch).await.unwrap();
+        }
+
+        let sent = node.lock().unwrap().sent_txs();
+        assert_eq!(sent.len(), 3);
+        let gas_prices: Vec<_> = sent.iter().map(|(tx, _)| tx.gas_price().unwrap()).collect();
+        assert!(gas_prices[0] < gas_prices[1] && gas_prices[1] < gas_prices[2]);
+        assert!(sent.iter().all(|(tx, _)| tx.nonce() == Some(&nonce)));
+        assert_eq!(supervisor.replacements.latest(&original), Some(sent[2].1));
+        assert_eq!(supervisor.report.lock().unwrap().sped_up, 2);
+    }
+}
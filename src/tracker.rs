@@ -0,0 +1,516 @@
+use std::{
+    collections::{hash_map::Entry, BTreeMap, HashMap},
+    fmt,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use ethers::{
+    providers::{Middleware, Provider},
+    types::{Address, TransactionReceipt, TxHash, U256},
+};
+use serde::{Deserialize, Serialize};
+use tokio::{sync::mpsc, time::Instant};
+
+use crate::{
+    inclusion::InclusionWatcher,
+    revert,
+    submit_error::{SubmitError, SubmitErrorKind},
+    transport::Transport,
+    COUNTER_CLIENT,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TxStatus {
+    /// Sent, not yet settled.
+    Pending,
+    /// Included with a successful receipt and enough confirmations.
+    Mined,
+    /// Included with a failed receipt and enough confirmations.
+    Reverted,
+    /// Its nonce was consumed by a different transaction.
+    Replaced,
+    /// Unknown to the node when the wait timed out.
+    Dropped,
+    /// Still known to the node but not included when the wait timed out.
+    TimedOut,
+    /// Rejected on submission, never got a hash.
+    Failed,
+}
+
+impl fmt::Display for TxStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            TxStatus::Pending => "pending",
+            TxStatus::Mined => "mined",
+            TxStatus::Reverted => "reverted",
+            TxStatus::Replaced => "replaced",
+            TxStatus::Dropped => "dropped",
+            TxStatus::TimedOut => "timed_out",
+            TxStatus::Failed => "failed",
+        };
+        f.write_str(s)
+    }
+}
+
+/// One transaction issued by a workload, followed until it settles.
+#[derive(Debug, Clone)]
+pub struct SentTx {
+    pub idx: u64,
+    pub from: Address,
+    pub hash: Option<TxHash>,
+    pub nonce: Option<U256>,
+    /// When the workload started submitting the transaction.
+    pub sent_at: Instant,
+    /// Round-trip time of the submission RPC.
+    pub submit_latency: Duration,
+    /// When the inclusion was first observed: the arrival of the block with
+    /// a head subscription, the receipt otherwise.
+    pub included_at: Option<Instant>,
+    /// When the node announced the transaction as pending.
+    pub pending_at: Option<Instant>,
+    pub receipt: Option<TransactionReceipt>,
+    pub status: TxStatus,
+    pub error: Option<String>,
+    /// Category of the rejection of a failed tx.
+    pub error_kind: Option<SubmitErrorKind>,
+    /// Rejections on the way, recovered from or not.
+    pub submit_errors: Vec<SubmitErrorKind>,
+}
+
+impl SentTx {
+    pub fn submitted(idx: u64, from: Address, hash: TxHash, nonce: U256, sent_at: Instant) -> Self {
+        Self {
+            idx,
+            from,
+            hash: Some(hash),
+            nonce: Some(nonce),
+            sent_at,
+            submit_latency: sent_at.elapsed(),
+            included_at: None,
+            pending_at: None,
+            receipt: None,
+            status: TxStatus::Pending,
+            error: None,
+            error_kind: None,
+            submit_errors: vec![],
+        }
+    }
+
+    pub fn failed(
+        idx: u64,
+        from: Address,
+        nonce: Option<U256>,
+        sent_at: Instant,
+        error: String,
+    ) -> Self {
+        Self {
+            idx,
+            from,
+            hash: None,
+            nonce,
+            sent_at,
+            submit_latency: sent_at.elapsed(),
+            included_at: None,
+            pending_at: None,
+            receipt: None,
+            status: TxStatus::Failed,
+            error: Some(error),
+            error_kind: None,
+            submit_errors: vec![],
+        }
+    }
+
+    /// A tx the node rejected for good.
+    pub fn rejected(
+        idx: u64,
+        from: Address,
+        nonce: U256,
+        sent_at: Instant,
+        error: SubmitError,
+    ) -> Self {
+        let mut tx = Self::failed(idx, from, Some(nonce), sent_at, error.message);
+        tx.error_kind = Some(error.kind);
+        tx
+    }
+
+    pub fn with_submit_errors(mut self, errors: Vec<SubmitErrorKind>) -> Self {
+        self.submit_errors = errors;
+        self
+    }
+}
+
+/// Transactions re-sent at the nonce of a tracked one with the same call,
+/// e.g. with bumped fees, so the tracker follows whichever gets mined.
+#[derive(Debug, Clone, Default)]
+pub struct Replacements(Arc<Mutex<HashMap<TxHash, TxHash>>>);
+
+impl Replacements {
+    pub fn record(&self, replaced: TxHash, replacement: TxHash) {
+        let mut replacements = self.0.lock().unwrap();
+        // Chains of replacements all point back at the submitted hash.
+        let original = replacements
+            .iter()
+            .find_map(|(original, latest)| (*latest == replaced).then_some(*original))
+            .unwrap_or(replaced);
+        replacements.insert(original, replacement);
+    }
+
+    pub fn latest(&self, original: &TxHash) -> Option<TxHash> {
+        self.0.lock().unwrap().get(original).copied()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TrackerConfig {
+    /// Blocks on top of the inclusion block, counting it, before a receipt is final.
+    pub confirmations: u64,
+    /// How long to wait for all transactions to settle after the last send.
+    pub timeout: Duration,
+    pub poll_interval: Duration,
+}
+
+impl Default for TrackerConfig {
+    fn default() -> Self {
+        Self {
+            confirmations: 1,
+            timeout: Duration::from_secs(60),
+            poll_interval: Duration::from_secs(1),
+        }
+    }
+}
+
+/// Follows every sent transaction to its receipt, replacement or timeout.
+pub struct TxTracker {
+    provider: Provider<Transport>,
+    config: TrackerConfig,
+    txs: Vec<SentTx>,
+    watcher: Option<InclusionWatcher>,
+    replacements: Replacements,
+}
+
+impl TxTracker {
+    pub fn new(provider: Provider<Transport>, config: TrackerConfig) -> Self {
+        Self {
+            provider,
+            config,
+            txs: vec![],
+            watcher: None,
+            replacements: Replacements::default(),
+        }
+    }
+
+    /// Takes inclusion times from subscriptions and re-checks on every new
+    /// head, polling as well when heads are late or stop.
+    pub fn with_watcher(mut self, watcher: Option<InclusionWatcher>) -> Self {
+        self.watcher = watcher;
+        self
+    }
+
+    /// Follows re-sent transactions as well as the submitted ones.
+    pub fn with_replacements(mut self, replacements: Replacements) -> Self {
+        self.replacements = replacements;
+        self
+    }
+
+    pub fn push(&mut self, tx: SentTx) {
+        if let (Some(watcher), Some(hash)) = (&self.watcher, tx.hash) {
+            watcher.track(hash);
+        }
+        self.txs.push(tx);
+    }
+
+    pub fn extend(&mut self, txs: impl IntoIterator<Item = SentTx>) {
+        for tx in txs {
+            self.push(tx);
+        }
+    }
+
+    pub fn txs(&self) -> &[SentTx] {
+        &self.txs
+    }
+
+    pub fn into_txs(self) -> Vec<SentTx> {
+        self.txs
+    }
+
+    /// Takes transactions as the workload submits them and polls their
+    /// receipts meanwhile, so inclusion is timed while sending rather than
+    /// from the end of the run. Returns once every sender is dropped.
+    pub async fn follow(&mut self, mut sent: mpsc::UnboundedReceiver<SentTx>) {
+        let mut next_poll = Instant::now() + self.config.poll_interval;
+        loop {
+            let tick = async {
+                let poll_due = tokio::time::sleep_until(next_poll);
+                match &self.watcher {
+                    // Heads usually come first, the poll keeps receipts
+                    // coming should the head stream stop.
+                    Some(watcher) => tokio::select! {
+                        _ = watcher.next_head() => {}
+                        _ = poll_due => {}
+                    },
+                    None => poll_due.await,
+                }
+            };
+            let received = tokio::select! {
+                tx = sent.recv() => Some(tx),
+                _ = tick => None,
+            };
+            match received {
+                Some(Some(tx)) => self.push(tx),
+                Some(None) => break,
+                // A failed poll is retried on the next tick, `settle` reports
+                // one that keeps failing.
+                None => {
+                    if let Err(err) = self.poll().await {
+                        tracing::warn!(target: COUNTER_CLIENT, "receipt poll failed with err: {}", err);
+                    }
+                    next_poll = Instant::now() + self.config.poll_interval;
+                }
+            }
+        }
+        self.txs.sort_by_key(|tx| tx.idx);
+    }
+
+    /// Polls until every transaction is settled or the timeout expires.
+    pub async fn settle(&mut self) -> anyhow::Result<()> {
+        let deadline = Instant::now() + self.config.timeout;
+        loop {
+            self.poll().await?;
+            let pending = self.pending_count();
+            if pending == 0 {
+                return Ok(());
+            }
+            if Instant::now() >= deadline {
+                tracing::warn!(
+                    target: COUNTER_CLIENT,
+                    "{} txs not settled after {:?}",
+                    pending,
+                    self.config.timeout
+                );
+                return self.expire().await;
+            }
+            tracing::debug!(target: COUNTER_CLIENT, "waiting for {} txs to settle", pending);
+            let poll_due = tokio::time::sleep(self.config.poll_interval);
+            match &self.watcher {
+                Some(watcher) => {
+                    tokio::select! {
+                        _ = watcher.next_head() => {}
+                        _ = poll_due => {}
+                        _ = tokio::time::sleep_until(deadline) => {}
+                    }
+                }
+                None => poll_due.await,
+            }
+        }
+    }
+
+    async fn poll(&mut self) -> anyhow::Result<()> {
+        // Read the nonces before the receipts: a slot consumed by then without
+        // a receipt for our hash can only have been taken by another tx.
+        let mut confirmed_nonces = HashMap::new();
+        for tx in self.txs.iter().filter(|tx| tx.status == TxStatus::Pending) {
+            if let Entry::Vacant(entry) = confirmed_nonces.entry(tx.from) {
+                entry.insert(self.provider.get_transaction_count(tx.from, None).await?);
+            }
+        }
+        let head = self.provider.get_block_number().await?;
+
+        for tx in self.txs.iter_mut().filter(|tx| tx.status == TxStatus::Pending) {
+            let Some(hash) = tx.hash else { continue };
+            let watcher = self.watcher.as_ref();
+            if tx.pending_at.is_none() {
+                tx.pending_at = watcher.and_then(|w| w.pending_at(&hash));
+            }
+            let mut receipt = self.provider.get_transaction_receipt(hash).await?;
+            if receipt.is_none() {
+                if let Some(replacement) = self.replacements.latest(&hash) {
+                    if let Some(watcher) = watcher {
+                        watcher.track(replacement);
+                    }
+                    receipt = self.provider.get_transaction_receipt(replacement).await?;
+                }
+            }
+            match receipt {
+                Some(receipt) => {
+                    if tx.included_at.is_none() {
+                        let seen = watcher.and_then(|w| w.included_at(&receipt.transaction_hash));
+                        tx.included_at = Some(seen.unwrap_or_else(Instant::now));
+                    }
+                    let Some(block) = receipt.block_number else {
+                        continue;
+                    };
+                    if head.as_u64() + 1 >= block.as_u64() + self.config.confirmations {
+                        tx.status = if receipt.status.is_some_and(|s| s.as_u64() == 1) {
+                            TxStatus::Mined
+                        } else {
+                            tx.error = Some(revert::describe_receipt(&self.provider, &receipt).await);
+                            TxStatus::Reverted
+                        };
+                    }
+                    tx.receipt = Some(receipt);
+                }
+                None => {
+                    // A receipt seen earlier and gone now was reorged out.
+                    tx.receipt = None;
+                    tx.included_at = None;
+                    if matches!(tx.nonce, Some(nonce) if nonce < confirmed_nonces[&tx.from]) {
+                        tx.status = TxStatus::Replaced;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn expire(&mut self) -> anyhow::Result<()> {
+        for tx in self.txs.iter_mut().filter(|tx| tx.status == TxStatus::Pending) {
+            // Included but short of the confirmation depth counts as timed out too.
+            let hashes = tx
+                .hash
+                .into_iter()
+                .chain(tx.hash.and_then(|hash| self.replacements.latest(&hash)));
+            let mut known = false;
+            for hash in hashes {
+                known |= self.provider.get_transaction(hash).await?.is_some();
+            }
+            tx.status = if known {
+                TxStatus::TimedOut
+            } else {
+                TxStatus::Dropped
+            };
+        }
+        Ok(())
+    }
+
+    pub fn pending_count(&self) -> usize {
+        self.txs
+            .iter()
+            .filter(|tx| tx.status == TxStatus::Pending)
+            .count()
+    }
+
+    pub fn count(&self, status: TxStatus) -> usize {
+        self.txs.iter().filter(|tx| tx.status == status).count()
+    }
+
+    /// Logs every transaction that did not end up mined, then the totals.
+    pub fn log_outcome(&self) {
+        let mut totals = BTreeMap::new();
+        for tx in &self.txs {
+            *totals.entry(tx.status).or_insert(0usize) += 1;
+            if tx.status != TxStatus::Mined {
+                tracing::warn!(
+                    target: COUNTER_CLIENT,
+                    "idx:{} from:{:?} hash:{:?} nonce:{:?} {}{}",
+                    tx.idx,
+                    tx.from,
+                    tx.hash,
+                    tx.nonce,
+                    tx.status,
+                    tx.error
+                        .as_ref()
+                        .map(|e| format!(" err: {}", e))
+                        .unwrap_or_default()
+                );
+            }
+        }
+        let totals: Vec<_> = totals
+            .iter()
+            .map(|(status, n)| format!("{}={}", status, n))
+            .collect();
+        tracing::info!(target: COUNTER_CLIENT, "tx outcome: {}", totals.join(" "));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::mock_rpc;
+
+    /// Inclusion latency of a tx sent at the start of a run of `run_length`
+    /// and mined after `unmined_polls` receipt polls.
+    async fn inclusion_latency(
+        run_length: Duration,
+        unmined_polls: usize,
+        watcher: Option<InclusionWatcher>,
+    ) -> Duration {
+        let polls = AtomicUsize::new(0);
+        let provider = mock_rpc::provider(move |method, params| match method {
+            // The nonce counts as used once the receipt is there.
+            "eth_getTransactionCount" if polls.load(Ordering::Relaxed) < unmined_polls => {
+                Ok(json!("0x0"))
+            }
+            "eth_getTransactionCount" => Ok(json!("0x1")),
+            "eth_blockNumber" => Ok(json!("0x1")),
+            "eth_getTransactionReceipt"
+                if polls.fetch_add(1, Ordering::Relaxed) < unmined_polls =>
+            {
+                Ok(json!(null))
+            }
+            "eth_getTransactionReceipt" => Ok(mock_rpc::receipt(&params[0], 1)),
+            _ => Err(format!("unexpected {}", method)),
+        })
+        .await;
+        let config = TrackerConfig {
+            confirmations: 1,
+            timeout: Duration::from_secs(5),
+            poll_interval: Duration::from_millis(20),
+        };
+        let mut tracker = TxTracker::new(provider, config).with_watcher(watcher);
+        let (sent_tx, sent_rx) = mpsc::unbounded_channel();
+        let send = async move {
+            let tx = SentTx::submitted(
+                0,
+                Address::zero(),
+                TxHash::repeat_byte(1),
+                U256::zero(),
+                Instant::now(),
+            );
+            sent_tx.send(tx).unwrap();
+            tokio::time::sleep(run_length).await;
+        };
+        tokio::join!(send, tracker.follow(sent_rx));
+        tracker.settle().await.unwrap();
+
+        let tx = &tracker.txs()[0];
+        assert_eq!(tx.status, TxStatus::Mined);
+        tx.included_at.unwrap() - tx.sent_at
+    }
+
+    #[tokio::test]
+    async fn inclusion_latency_does_not_grow_with_run_length() {
+        let short = inclusion_latency(Duration::from_millis(50), 0, None).await;
+        let long = inclusion_latency(Duration::from_secs(1), 0, None).await;
+        assert!(short < Duration::from_millis(500), "{:?}", short);
+        assert!(long < Duration::from_millis(500), "{:?}", long);
+    }
+
+    #[tokio::test]
+    async fn polls_when_the_head_stream_is_silent() {
+        // Mined while sending, and only after the run while settling; the
+        // timeout is 5s.
+        for (run_length, unmined_polls) in [(Duration::from_millis(200), 2), (Duration::ZERO, 5)] {
+            let watcher = InclusionWatcher::without_heads();
+            let latency = inclusion_latency(run_length, unmined_polls, Some(watcher)).await;
+            assert!(latency < Duration::from_millis(500), "{:?}", latency);
+        }
+    }
+
+    #[test]
+    fn replacements_chain_back_to_the_submitted_hash() {
+        let [submitted, first, second] = [1, 2, 3].map(TxHash::repeat_byte);
+        let replacements = Replacements::default();
+        replacements.record(submitted, first);
+        assert_eq!(replacements.latest(&submitted), Some(first));
+
+        replacements.record(first, second);
+        assert_eq!(replacements.latest(&submitted), Some(second));
+        assert_eq!(replacements.latest(&first), None);
+    }
+}
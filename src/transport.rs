@@ -0,0 +1,215 @@
+use std::{fmt::Debug, str::FromStr, sync::Arc};
+
+use async_trait::async_trait;
+use ethers::{
+    providers::{
+        Http, HttpClientError, Ipc, IpcError, JsonRpcClient, JsonRpcError, ProviderError,
+        PubsubClient, RpcError, Ws, WsClientError,
+    },
+    types::U256,
+};
+use futures_util::stream::{BoxStream, StreamExt};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::value::RawValue;
+
+use crate::{config::redact_url, endpoints::EndpointPool};
+
+/// RPC transport picked from the scheme of the endpoint: `http(s)://`,
+/// `ws(s)://`, or `ipc://<path>` / a bare socket path.
+#[derive(Debug, Clone)]
+pub enum Transport {
+    Http(Http),
+    Ws(Ws),
+    Ipc(Ipc),
+    /// Several endpoints behind routing, retries and failover.
+    Pool(Arc<EndpointPool>),
+    /// Submissions and pending nonces go to `write`, e.g. the sequencer,
+    /// everything else to `read`, e.g. its replicas.
+    Split {
+        read: Box<Transport>,
+        write: Box<Transport>,
+    },
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum TransportError {
+    #[error(transparent)]
+    Http(#[from] HttpClientError),
+    #[error(transparent)]
+    Ws(#[from] WsClientError),
+    #[error(transparent)]
+    Ipc(#[from] IpcError),
+    #[error("invalid url `{0}`")]
+    Url(String),
+    #[error("{0} transport does not support subscriptions")]
+    NoPubsub(&'static str),
+    #[error("no RPC endpoint available")]
+    NoEndpoint,
+    #[error(transparent)]
+    Serde(#[from] serde_json::Error),
+}
+
+impl Transport {
+    pub async fn connect(endpoint: &str) -> Result<Self, TransportError> {
+        let scheme = endpoint
+            .split_once("://")
+            .map(|(scheme, rest)| (scheme.to_ascii_lowercase(), rest));
+        let transport = match scheme {
+            Some((scheme, _)) if scheme == "ws" || scheme == "wss" => {
+                Transport::Ws(Ws::connect(endpoint).await?)
+            }
+            Some((scheme, path)) if scheme == "ipc" => Transport::Ipc(Ipc::connect(path).await?),
+            Some(_) => Transport::Http(
+                Http::from_str(endpoint).map_err(|_| TransportError::Url(redact_url(endpoint)))?,
+            ),
+            None => Transport::Ipc(Ipc::connect(endpoint).await?),
+        };
+        Ok(transport)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Transport::Http(_) => "http",
+            Transport::Ws(_) => "ws",
+            Transport::Ipc(_) => "ipc",
+            Transport::Pool(_) => "pool",
+            Transport::Split { .. } => "split",
+        }
+    }
+
+    /// Whether `eth_subscribe` is available.
+    pub fn is_pubsub(&self) -> bool {
+        match self {
+            Transport::Http(_) => false,
+            Transport::Ws(_) | Transport::Ipc(_) => true,
+            Transport::Pool(pool) => pool.is_pubsub(),
+            Transport::Split { read, .. } => read.is_pubsub(),
+        }
+    }
+
+    /// The endpoint pool reads are served from.
+    pub fn pool(&self) -> Option<&Arc<EndpointPool>> {
+        match self {
+            Transport::Pool(pool) => Some(pool),
+            Transport::Split { read, .. } => read.pool(),
+            _ => None,
+        }
+    }
+
+    /// The read side of a split transport, itself otherwise.
+    pub fn read(&self) -> &Transport {
+        match self {
+            Transport::Split { read, .. } => read,
+            other => other,
+        }
+    }
+
+    /// The write side of a split transport, `None` otherwise.
+    pub fn write(&self) -> Option<&Transport> {
+        match self {
+            Transport::Split { write, .. } => Some(write),
+            _ => None,
+        }
+    }
+}
+
+#[async_trait]
+impl JsonRpcClient for Transport {
+    type Error = TransportError;
+
+    async fn request<T, R>(&self, method: &str, params: T) -> Result<R, Self::Error>
+    where
+        T: Debug + Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+    {
+        Ok(match self {
+            Transport::Http(http) => http.request(method, params).await?,
+            Transport::Ws(ws) => ws.request(method, params).await?,
+            Transport::Ipc(ipc) => ipc.request(method, params).await?,
+            Transport::Pool(pool) => pool.request(method, params).await?,
+            Transport::Split { read, write } => {
+                if is_write(method, &params) {
+                    write.request(method, params).await?
+                } else {
+                    read.request(method, params).await?
+                }
+            }
+        })
+    }
+}
+
+impl PubsubClient for Transport {
+    type NotificationStream = BoxStream<'static, Box<RawValue>>;
+
+    fn subscribe<T: Into<U256>>(&self, id: T) -> Result<Self::NotificationStream, Self::Error> {
+        Ok(match self {
+            Transport::Http(_) => return Err(TransportError::NoPubsub(self.name())),
+            Transport::Ws(ws) => ws.subscribe(id)?.boxed(),
+            Transport::Ipc(ipc) => ipc.subscribe(id)?.boxed(),
+            Transport::Pool(pool) => pool.subscribe(id)?,
+            Transport::Split { read, .. } => read.subscribe(id)?,
+        })
+    }
+
+    fn unsubscribe<T: Into<U256>>(&self, id: T) -> Result<(), Self::Error> {
+        match self {
+            Transport::Http(_) => Err(TransportError::NoPubsub(self.name())),
+            Transport::Ws(ws) => Ok(ws.unsubscribe(id)?),
+            Transport::Ipc(ipc) => Ok(ipc.unsubscribe(id)?),
+            Transport::Pool(pool) => pool.unsubscribe(id),
+            Transport::Split { read, .. } => read.unsubscribe(id),
+        }
+    }
+}
+
+/// Requests a split transport sends to its write side. Pending nonces come
+/// from there too, a lagging replica would hand out used ones.
+fn is_write<T: Serialize>(method: &str, params: &T) -> bool {
+    match method {
+        "eth_sendRawTransaction" | "eth_sendTransaction" => true,
+        "eth_getTransactionCount" => serde_json::to_value(params)
+            .ok()
+            .and_then(|params| params.get(1).cloned())
+            .is_some_and(|block| block == "pending"),
+        _ => false,
+    }
+}
+
+impl RpcError for TransportError {
+    fn as_error_response(&self) -> Option<&JsonRpcError> {
+        match self {
+            TransportError::Http(err) => err.as_error_response(),
+            TransportError::Ws(err) => err.as_error_response(),
+            TransportError::Ipc(err) => err.as_error_response(),
+            TransportError::Serde(_)
+            | TransportError::Url(_)
+            | TransportError::NoPubsub(_)
+            | TransportError::NoEndpoint => None,
+        }
+    }
+
+    fn as_serde_error(&self) -> Option<&serde_json::Error> {
+        match self {
+            TransportError::Http(err) => err.as_serde_error(),
+            TransportError::Ws(err) => err.as_serde_error(),
+            TransportError::Ipc(err) => err.as_serde_error(),
+            TransportError::Serde(err) => Some(err),
+            TransportError::Url(_) | TransportError::NoPubsub(_) | TransportError::NoEndpoint => None,
+        }
+    }
+}
+
+impl From<TransportError> for ProviderError {
+    fn from(err: TransportError) -> Self {
+        match err {
+            TransportError::Http(err) => err.into(),
+            TransportError::Ws(err) => err.into(),
+            TransportError::Ipc(err) => err.into(),
+            TransportError::NoPubsub(_) => ProviderError::UnsupportedRPC,
+            TransportError::Serde(err) => ProviderError::SerdeJson(err),
+            err @ (TransportError::Url(_) | TransportError::NoEndpoint) => {
+                ProviderError::CustomError(err.to_string())
+            }
+        }
+    }
+}
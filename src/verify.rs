@@ -0,0 +1,206 @@
+use std::fmt;
+
+use ethers::types::U256;
+
+use crate::{
+    bindings::lock::Lock,
+    tracker::{SentTx, TxStatus},
+    SignerProvider,
+};
+
+/// On-chain `Lock::counter` read at a pinned block.
+#[derive(Debug, Clone, Copy)]
+pub struct CounterSnapshot {
+    pub block: u64,
+    pub value: U256,
+}
+
+impl CounterSnapshot {
+    pub async fn read(contract: &Lock<SignerProvider>, block: u64) -> anyhow::Result<Self> {
+        let value = contract.counter().block(block).call().await?;
+        Ok(Self { block, value })
+    }
+}
+
+/// Compares the on-chain counter delta with the successful `inc()` receipts
+/// that landed between the two snapshots.
+#[derive(Debug, Clone)]
+pub struct CounterCheck {
+    pub start: CounterSnapshot,
+    pub end: CounterSnapshot,
+    pub expected_delta: u64,
+    /// Successful receipts that are not final yet but still fall inside the window.
+    pub unconfirmed_success: u64,
+    /// Txs whose fate is unknown and could still move the counter later.
+    pub unsettled: Vec<SentTx>,
+}
+
+impl CounterCheck {
+    pub fn new(start: CounterSnapshot, end: CounterSnapshot, txs: &[SentTx]) -> Self {
+        let mut expected_delta = 0;
+        let mut unconfirmed_success = 0;
+        let mut unsettled = vec![];
+        for tx in txs {
+            let landed = tx.receipt.as_ref().and_then(|receipt| {
+                let block = receipt.block_number?.as_u64();
+                let success = receipt.status.is_some_and(|s| s.as_u64() == 1);
+                Some(success && block > start.block && block <= end.block)
+            });
+            if landed == Some(true) {
+                expected_delta += 1;
+                if tx.status != TxStatus::Mined {
+                    unconfirmed_success += 1;
+                }
+            } else if matches!(
+                tx.status,
+                TxStatus::Pending | TxStatus::TimedOut | TxStatus::Dropped
+            ) {
+                unsettled.push(tx.clone());
+            }
+        }
+        Self {
+            start,
+            end,
+            expected_delta,
+            unconfirmed_success,
+            unsettled,
+        }
+    }
+
+    pub fn actual_delta(&self) -> i128 {
+        self.end.value.as_u128() as i128 - self.start.value.as_u128() as i128
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.actual_delta() == self.expected_delta as i128
+    }
+}
+
+impl fmt::Display for CounterCheck {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "counter check {}: block {} value {} -> block {} value {}",
+            if self.is_ok() { "passed" } else { "FAILED" },
+            self.start.block,
+            self.start.value,
+            self.end.block,
+            self.end.value,
+        )?;
+        writeln!(
+            f,
+            "    expected delta {} ({} not yet final), actual delta {}, difference {}",
+            self.expected_delta,
+            self.unconfirmed_success,
+            self.actual_delta(),
+            self.actual_delta() - self.expected_delta as i128,
+        )?;
+        if self.is_ok() {
+            return Ok(());
+        }
+        if self.actual_delta() > self.expected_delta as i128 {
+            writeln!(
+                f,
+                "    counter moved more than expected: another sender called inc() or an untracked tx landed"
+            )?;
+        } else {
+            writeln!(
+                f,
+                "    counter moved less than expected: successful receipts are not reflected in state"
+            )?;
+        }
+        for tx in &self.unsettled {
+            writeln!(
+                f,
+                "    unsettled idx:{} hash:{:?} nonce:{:?} {}",
+                tx.idx, tx.hash, tx.nonce, tx.status
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers::types::{Address, TransactionReceipt, TxHash};
+    use tokio::time::Instant;
+
+    use super::*;
+
+    fn snapshot(block: u64, value: u64) -> CounterSnapshot {
+        CounterSnapshot {
+            block,
+            value: value.into(),
+        }
+    }
+
+    /// A tx in `status`, with a receipt when it landed in `block`.
+    fn tx(idx: u64, status: TxStatus, landed: Option<(u64, bool)>) -> SentTx {
+        let mut tx = SentTx::submitted(
+            idx,
+            Address::zero(),
+            TxHash::from_low_u64_be(idx),
+            idx.into(),
+            Instant::now(),
+        );
+        tx.status = status;
+        tx.receipt = landed.map(|(block, success)| TransactionReceipt {
+            block_number: Some(block.into()),
+            status: Some((success as u64).into()),
+            ..Default::default()
+        });
+        tx
+    }
+
+    #[test]
+    fn counts_successful_receipts_inside_the_window() {
+        let txs = [
+            tx(0, TxStatus::Mined, Some((11, true))),
+            tx(1, TxStatus::Mined, Some((20, true))),
+            // Confirmations pending, but the receipt already moved the counter.
+            tx(2, TxStatus::Pending, Some((20, true))),
+            tx(3, TxStatus::Reverted, Some((12, false))),
+            // Outside the window.
+            tx(4, TxStatus::Mined, Some((10, true))),
+            tx(5, TxStatus::Mined, Some((21, true))),
+            tx(6, TxStatus::Failed, None),
+        ];
+        let check = CounterCheck::new(snapshot(10, 100), snapshot(20, 103), &txs);
+        assert_eq!(check.expected_delta, 3);
+        assert_eq!(check.unconfirmed_success, 1);
+        assert!(check.unsettled.is_empty());
+        assert_eq!(check.actual_delta(), 3);
+        assert!(check.is_ok());
+        assert!(check.to_string().starts_with("counter check passed"));
+    }
+
+    #[test]
+    fn reports_mismatches_and_unsettled_txs() {
+        let txs = [
+            tx(0, TxStatus::Mined, Some((11, true))),
+            tx(1, TxStatus::TimedOut, None),
+            tx(2, TxStatus::Dropped, None),
+            tx(3, TxStatus::Pending, None),
+        ];
+        let check = CounterCheck::new(snapshot(10, 100), snapshot(20, 102), &txs);
+        assert_eq!(check.expected_delta, 1);
+        assert_eq!(
+            check.unsettled.iter().map(|tx| tx.idx).collect::<Vec<_>>(),
+            [1, 2, 3]
+        );
+        assert!(!check.is_ok());
+        let report = check.to_string();
+        assert!(report.starts_with("counter check FAILED"), "{}", report);
+        assert!(
+            report.contains("counter moved more than expected"),
+            "{}",
+            report
+        );
+
+        let check = CounterCheck::new(snapshot(10, 100), snapshot(20, 100), &txs);
+        assert_eq!(check.actual_delta(), 0);
+        assert!(check
+            .to_string()
+            .contains("counter moved less than expected"));
+    }
+}
@@ -0,0 +1,111 @@
+use std::sync::Arc;
+
+use ethers::{
+    providers::{Middleware, Provider},
+    signers::{coins_bip39::English, MnemonicBuilder},
+    types::{Address, TransactionRequest, U256},
+    utils::format_ether,
+};
+
+use crate::{
+    get_wallet_signer_provider, secret, transport::Transport, SignerProvider, COUNTER_CLIENT,
+};
+
+/// Default BIP-44 Ethereum path, the account index is appended.
+pub const DEFAULT_DERIVATION_PATH: &str = "m/44'/60'/0'/0/";
+
+/// Sender accounts the load is spread across, each with its own signer and
+/// nonce manager.
+pub struct WalletPool {
+    accounts: Vec<Arc<SignerProvider>>,
+}
+
+impl WalletPool {
+    /// A pool of just the operator account.
+    pub fn single(account: Arc<SignerProvider>) -> Self {
+        Self {
+            accounts: vec![account],
+        }
+    }
+
+    /// Derives `count` accounts at `<derivation_path><index>` starting from
+    /// `start_index`.
+    pub fn derive(
+        provider: &Provider<Transport>,
+        chain_id: u64,
+        mnemonic: &str,
+        derivation_path: &str,
+        start_index: u32,
+        count: u32,
+    ) -> anyhow::Result<Self> {
+        let mut accounts = vec![];
+        for index in start_index..start_index + count {
+            let wallet = MnemonicBuilder::<English>::default()
+                .phrase(mnemonic)
+                .derivation_path(&format!("{}{}", derivation_path, index))?
+                .build()?;
+            secret::register_key(&wallet);
+            accounts.push(Arc::new(get_wallet_signer_provider(
+                provider.clone(),
+                chain_id,
+                wallet,
+            )));
+        }
+        Ok(Self { accounts })
+    }
+
+    pub fn accounts(&self) -> &[Arc<SignerProvider>] {
+        &self.accounts
+    }
+
+    pub fn addresses(&self) -> Vec<Address> {
+        self.accounts
+            .iter()
+            .map(|account| account.inner().address())
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.accounts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.accounts.is_empty()
+    }
+
+    /// Tops every account below `target` up to it from `funder` and waits for
+    /// the transfers to be mined.
+    pub async fn fund(&self, funder: &SignerProvider, target: U256) -> anyhow::Result<()> {
+        let funder_address = funder.inner().address();
+        let mut pending = vec![];
+        for address in self.addresses() {
+            if address == funder_address {
+                continue;
+            }
+            let balance = funder.get_balance(address, None).await?;
+            if balance >= target {
+                continue;
+            }
+            let amount = target - balance;
+            let tx = funder
+                .send_transaction(TransactionRequest::pay(address, amount), None)
+                .await?;
+            tracing::info!(
+                target: COUNTER_CLIENT,
+                "funding {:?} with {} eth in tx {:?}",
+                address,
+                format_ether(amount),
+                *tx
+            );
+            pending.push((address, tx));
+        }
+
+        for (address, tx) in pending {
+            match tx.await? {
+                Some(receipt) if receipt.status.is_some_and(|s| s.as_u64() == 1) => {}
+                _ => anyhow::bail!("funding transfer to {:?} failed", address),
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,496 @@
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{anyhow, Context as _};
+use ethers::{
+    contract::{parse_log, EthEvent},
+    providers::{Middleware, Provider, ProviderError, RpcError},
+    types::{Address, BlockId, BlockNumber, Filter, Log, TxHash, H256},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{bindings::lock::WithdrawalFilter, transport::Transport, COUNTER_CLIENT};
+
+/// Last block whose events were fully processed, with its hash so a restart
+/// can tell whether the chain reorganized underneath it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub chain_id: u64,
+    pub address: Address,
+    pub block_number: u64,
+    pub block_hash: H256,
+    /// Events of the blocks a rewind reads again, so that they are not
+    /// emitted twice across a restart either.
+    #[serde(default)]
+    pub recent_events: Vec<EmittedEvent>,
+}
+
+/// Where an emitted event was, unique as long as its block is canonical.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct EmittedEvent {
+    pub block_number: u64,
+    pub block_hash: H256,
+    pub log_index: u64,
+}
+
+impl Checkpoint {
+    pub fn path(dir: &Path, chain_id: u64) -> PathBuf {
+        dir.join(format!("watch-{}.json", chain_id))
+    }
+
+    /// Written aside and renamed over, a crash leaves the previous one.
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_string_pretty(self)?)?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    pub fn load(path: &Path) -> anyhow::Result<Option<Self>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        let checkpoint = serde_json::from_str(&fs::read_to_string(path)?)
+            .with_context(|| format!("reading {}", path.display()))?;
+        Ok(Some(checkpoint))
+    }
+}
+
+/// A decoded `Withdrawal` with where it was emitted.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WithdrawalRecord {
+    pub block_number: u64,
+    pub block_hash: H256,
+    pub tx_hash: TxHash,
+    pub tx_index: Option<u64>,
+    pub log_index: Option<u64>,
+    /// In wei.
+    pub amount: String,
+    pub when: u64,
+}
+
+impl WithdrawalRecord {
+    /// `None` for logs without a block, i.e. pending ones.
+    fn new(event: &WithdrawalFilter, log: &Log) -> Option<Self> {
+        Some(Self {
+            block_number: log.block_number?.as_u64(),
+            block_hash: log.block_hash?,
+            tx_hash: log.transaction_hash?,
+            tx_index: log.transaction_index.map(|index| index.as_u64()),
+            log_index: log.log_index.map(|index| index.as_u64()),
+            amount: event.amount.to_string(),
+            when: event.when.as_u64(),
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct WatchConfig {
+    /// Stop after this block instead of following new ones.
+    pub to_block: Option<u64>,
+    /// Blocks per `eth_getLogs`, halved while the provider rejects the range
+    /// and doubled back after every chunk that went through.
+    pub chunk_size: u64,
+    /// Blocks behind the head before events are processed.
+    pub confirmations: u64,
+    /// How often the head is checked once caught up.
+    pub poll_interval: Duration,
+}
+
+impl Default for WatchConfig {
+    fn default() -> Self {
+        Self {
+            to_block: None,
+            chunk_size: 2000,
+            confirmations: 6,
+            poll_interval: Duration::from_secs(2),
+        }
+    }
+}
+
+/// The withdrawals of a range of processed blocks.
+#[derive(Debug, Clone)]
+pub struct WatchChunk {
+    pub from: u64,
+    pub to: u64,
+    pub withdrawals: Vec<(WithdrawalFilter, WithdrawalRecord)>,
+    /// Resuming from it continues after `to`.
+    pub checkpoint: Checkpoint,
+}
+
+/// Reads the `Withdrawal` events of a `Lock` in chunks of confirmed blocks,
+/// first the backlog, then new blocks as they come.
+pub struct WithdrawalWatcher {
+    provider: Provider<Transport>,
+    chain_id: u64,
+    address: Address,
+    config: WatchConfig,
+    next: u64,
+    checkpoint: Option<Checkpoint>,
+    chunk_size: u64,
+    /// Events of the last blocks that a rewind may read again.
+    emitted: HashSet<EmittedEvent>,
+    /// Whether the checkpoint is to be checked against the chain before the
+    /// next chunk: on start and after every wait for new blocks.
+    verify: bool,
+}
+
+impl WithdrawalWatcher {
+    /// Starts at `from_block`. A `checkpoint` is checked to still be
+    /// canonical first, and the watcher rewinds past it if not.
+    pub fn new(
+        provider: Provider<Transport>,
+        chain_id: u64,
+        address: Address,
+        from_block: u64,
+        checkpoint: Option<Checkpoint>,
+        config: WatchConfig,
+    ) -> Self {
+        let chunk_size = config.chunk_size.max(1);
+        let emitted = checkpoint
+            .iter()
+            .flat_map(|checkpoint| checkpoint.recent_events.iter().copied())
+            .collect();
+        Self {
+            provider,
+            chain_id,
+            address,
+            config,
+            next: from_block,
+            checkpoint,
+            chunk_size,
+            emitted,
+            verify: true,
+        }
+    }
+
+    /// The first block not processed yet.
+    pub fn next_block(&self) -> u64 {
+        self.next
+    }
+
+    /// The next chunk of confirmed blocks, waiting for new blocks once caught
+    /// up. `None` once past `to_block`.
+    pub async fn next_chunk(&mut self) -> anyhow::Result<Option<WatchChunk>> {
+        loop {
+            if self.config.to_block.is_some_and(|to_block| self.next > to_block) {
+                return Ok(None);
+            }
+            if self.verify {
+                self.rewind_if_reorged().await?;
+                self.verify = false;
+            }
+
+            let safe = safe_head(&self.provider, self.config.confirmations).await?;
+            let end = self
+                .config
+                .to_block
+                .map_or(safe, |to_block| to_block.min(safe));
+            if self.next > end {
+                tokio::time::sleep(self.config.poll_interval).await;
+                self.verify = true;
+                continue;
+            }
+
+            let from = self.next;
+            let to = end.min(from + self.chunk_size - 1);
+            let filter = Filter::new()
+                .address(self.address)
+                .topic0(WithdrawalFilter::signature())
+                .from_block(from)
+                .to_block(to);
+            let logs = match self.provider.get_logs(&filter).await {
+                Ok(logs) => logs,
+                // Providers answer ranges over their log limit with an error
+                // response, retry with half the range until it fits.
+                Err(err) if self.chunk_size > 1 && err.as_error_response().is_some() => {
+                    self.halve_chunk(from, to, &err);
+                    continue;
+                }
+                Err(err) => return Err(err.into()),
+            };
+
+            self.grow_chunk();
+
+            let withdrawals = logs
+                .iter()
+                .filter(|log| log.removed != Some(true))
+                .filter_map(|log| {
+                    let event = parse_log::<WithdrawalFilter>(log.clone()).ok()?;
+                    let record = WithdrawalRecord::new(&event, log)?;
+                    Some((event, record))
+                })
+                .filter(|(_, record)| self.first_emission(record))
+                .collect();
+            // A rewind goes back `confirmations` blocks from the checkpoint.
+            let rewind = to.saturating_sub(self.config.confirmations.max(1));
+            self.emitted.retain(|event| event.block_number >= rewind);
+            let mut recent_events: Vec<_> = self.emitted.iter().copied().collect();
+            recent_events.sort();
+            let checkpoint = Checkpoint {
+                chain_id: self.chain_id,
+                address: self.address,
+                block_number: to,
+                block_hash: block_hash(&self.provider, to).await?,
+                recent_events,
+            };
+            self.checkpoint = Some(checkpoint.clone());
+            self.next = to + 1;
+            return Ok(Some(WatchChunk {
+                from,
+                to,
+                withdrawals,
+                checkpoint,
+            }));
+        }
+    }
+
+    fn halve_chunk(&mut self, from: u64, to: u64, err: &ProviderError) {
+        let attempted = to - from + 1;
+        self.chunk_size = (attempted / 2).max(1);
+        tracing::warn!(
+            target: COUNTER_CLIENT,
+            "eth_getLogs {}..={} failed with err: {}, retrying {} blocks at a time",
+            from,
+            to,
+            err,
+            self.chunk_size
+        );
+    }
+
+    /// Back toward the configured size once the provider accepts ranges again.
+    fn grow_chunk(&mut self) {
+        self.chunk_size = (self.chunk_size * 2).min(self.config.chunk_size.max(1));
+    }
+
+    /// False for an event a rewind read again.
+    fn first_emission(&mut self, record: &WithdrawalRecord) -> bool {
+        let Some(log_index) = record.log_index else {
+            return true;
+        };
+        self.emitted.insert(EmittedEvent {
+            block_number: record.block_number,
+            block_hash: record.block_hash,
+            log_index,
+        })
+    }
+
+    /// Blocks past a checkpoint that is no longer canonical were processed
+    /// off a fork, go back far enough to re-read them from the canonical chain.
+    async fn rewind_if_reorged(&mut self) -> anyhow::Result<()> {
+        let Some(stale) = self.checkpoint.as_ref() else {
+            return Ok(());
+        };
+        let block = self
+            .provider
+            .get_block(BlockId::Number(BlockNumber::Number(stale.block_number.into())))
+            .await?;
+        if block.and_then(|block| block.hash) == Some(stale.block_hash) {
+            return Ok(());
+        }
+        let rewind = stale
+            .block_number
+            .saturating_sub(self.config.confirmations.max(1));
+        tracing::warn!(
+            target: COUNTER_CLIENT,
+            "block {} {:?} is no longer canonical, rewinding to {}",
+            stale.block_number,
+            stale.block_hash,
+            rewind
+        );
+        self.next = self.next.min(rewind);
+        self.checkpoint = None;
+        Ok(())
+    }
+}
+
+/// Latest block deep enough to not be reorganized anymore.
+pub async fn safe_head(provider: &Provider<Transport>, confirmations: u64) -> anyhow::Result<u64> {
+    let head = provider.get_block_number().await?.as_u64();
+    Ok(head.saturating_sub(confirmations))
+}
+
+async fn block_hash(provider: &Provider<Transport>, number: u64) -> anyhow::Result<H256> {
+    provider
+        .get_block(BlockId::Number(BlockNumber::Number(number.into())))
+        .await?
+        .and_then(|block| block.hash)
+        .ok_or_else(|| anyhow!("the node returned no block {}", number))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use ethers::types::U64;
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::mock_rpc;
+
+    #[derive(Default)]
+    struct Chain {
+        head: u64,
+        /// Block 10 and later are replaced by other blocks.
+        reorged: bool,
+        /// Withdrawals, one per block.
+        withdrawals: Vec<u64>,
+        /// Ranges of the `eth_getLogs` that went through.
+        ranges: Vec<(u64, u64)>,
+    }
+
+    impl Chain {
+        fn hash(&self, number: u64) -> H256 {
+            match self.reorged && number >= 10 {
+                true => H256::from_low_u64_be(0xf000 + number),
+                false => H256::from_low_u64_be(number),
+            }
+        }
+    }
+
+    fn block_number(value: &Value) -> u64 {
+        serde_json::from_value::<U64>(value.clone())
+            .unwrap()
+            .as_u64()
+    }
+
+    /// Watches a node with `chain` whose `eth_getLogs` fails for more than
+    /// `limit` blocks from below block 100, from block 0 or the `checkpoint`.
+    async fn watch(
+        chain: Arc<Mutex<Chain>>,
+        limit: u64,
+        checkpoint: Option<Checkpoint>,
+        config: WatchConfig,
+    ) -> WithdrawalWatcher {
+        let address = Address::repeat_byte(0x10);
+        let provider = mock_rpc::provider(move |method, params| {
+            let mut chain = chain.lock().unwrap();
+            match method {
+                "eth_blockNumber" => Ok(json!(U64::from(chain.head))),
+                "eth_getBlockByNumber" => {
+                    let number = block_number(&params[0]);
+                    Ok(json!({
+                        "hash": chain.hash(number),
+                        "number": U64::from(number),
+                        "transactions": [],
+                        "uncles": [],
+                    }))
+                }
+                "eth_getLogs" => {
+                    let from = block_number(&params[0]["fromBlock"]);
+                    let to = block_number(&params[0]["toBlock"]);
+                    if from < 100 && to - from + 1 > limit {
+                        return Err("query returned more than 10000 results".to_string());
+                    }
+                    chain.ranges.push((from, to));
+                    let logs: Vec<_> = chain
+                        .withdrawals
+                        .iter()
+                        .filter(|&&number| (from..=to).contains(&number))
+                        .map(|&number| {
+                            json!({
+                                "address": address,
+                                "topics": [WithdrawalFilter::signature()],
+                                "data": format!("0x{:064x}{:064x}", 1000 + number, number),
+                                "blockNumber": U64::from(number),
+                                "blockHash": chain.hash(number),
+                                "transactionHash": H256::from_low_u64_be(0xa000 + number),
+                                "transactionIndex": "0x0",
+                                "logIndex": "0x0",
+                                "removed": false,
+                            })
+                        })
+                        .collect();
+                    Ok(json!(logs))
+                }
+                _ => Ok(Value::Null),
+            }
+        })
+        .await;
+        let from_block = checkpoint
+            .as_ref()
+            .map_or(0, |checkpoint| checkpoint.block_number + 1);
+        WithdrawalWatcher::new(provider, 1337, address, from_block, checkpoint, config)
+    }
+
+    #[tokio::test]
+    async fn rejected_ranges_are_halved_then_grow_back() {
+        let chain = Arc::new(Mutex::new(Chain {
+            head: 2000,
+            ..Default::default()
+        }));
+        let config = WatchConfig {
+            to_block: Some(999),
+            chunk_size: 400,
+            confirmations: 0,
+            poll_interval: Duration::from_millis(1),
+        };
+        let mut watcher = watch(chain.clone(), 100, None, config).await;
+
+        let mut chunks = vec![];
+        while let Some(chunk) = watcher.next_chunk().await.unwrap() {
+            chunks.push((chunk.from, chunk.to));
+        }
+        let expected = vec![(0, 99), (100, 299), (300, 699), (700, 999)];
+        assert_eq!(chunks, expected);
+        assert_eq!(chain.lock().unwrap().ranges, expected);
+    }
+
+    #[tokio::test]
+    async fn rewound_events_are_emitted_once() {
+        let chain = Arc::new(Mutex::new(Chain {
+            head: 12,
+            withdrawals: vec![5, 9, 10],
+            ..Default::default()
+        }));
+        let config = WatchConfig {
+            to_block: None,
+            chunk_size: 100,
+            confirmations: 2,
+            poll_interval: Duration::from_millis(1),
+        };
+        let mut watcher = watch(chain.clone(), 100, None, config.clone()).await;
+        let emitted = |chunk: &WatchChunk| -> Vec<(u64, H256)> {
+            chunk
+                .withdrawals
+                .iter()
+                .map(|(_, record)| (record.block_number, record.block_hash))
+                .collect()
+        };
+
+        let first = watcher.next_chunk().await.unwrap().unwrap();
+        assert_eq!((first.from, first.to), (0, 10));
+        let before = chain.lock().unwrap().hash(10);
+        assert_eq!(
+            emitted(&first),
+            vec![
+                (5, H256::from_low_u64_be(5)),
+                (9, H256::from_low_u64_be(9)),
+                (10, before)
+            ]
+        );
+        let restart = first.checkpoint.clone();
+        assert_eq!(restart.recent_events.len(), 2);
+
+        // Block 10 is replaced: blocks 8 to 10 are read again, only the event
+        // of the new block 10 is new.
+        chain.lock().unwrap().reorged = true;
+        let after = chain.lock().unwrap().hash(10);
+        let second = watcher.next_chunk().await.unwrap().unwrap();
+        assert_eq!((second.from, second.to), (8, 10));
+        assert_eq!(emitted(&second), vec![(10, after)]);
+
+        // The same after a restart from the checkpoint of the first chunk.
+        let mut restarted = watch(chain.clone(), 100, Some(restart), config).await;
+        let third = restarted.next_chunk().await.unwrap().unwrap();
+        assert_eq!((third.from, third.to), (8, 10));
+        assert_eq!(emitted(&third), vec![(10, after)]);
+    }
+}
@@ -0,0 +1,416 @@
+use std::sync::{Arc, OnceLock};
+
+use ethers::{
+    providers::Middleware,
+    signers::Signer,
+    types::{transaction::eip2718::TypedTransaction, Address, TxHash, U256},
+};
+use tokio::{
+    sync::{mpsc, Semaphore},
+    time::Instant,
+};
+
+use crate::{
+    bindings::lock::Lock,
+    fees::{FeeOracle, Fees},
+    load::Scheduler,
+    nonce::NonceAllocator,
+    submit_error::{Recovery, RecoveryPolicy, SubmitError, SubmitErrorKind},
+    tracker::SentTx,
+    wallet_pool::WalletPool,
+    SignerProvider, SignerWithoutNonceProvider, COUNTER_CLIENT,
+};
+
+/// Bounds on submissions whose RPC call has not returned yet.
+#[derive(Debug, Clone, Copy)]
+pub struct InFlightWindow {
+    pub per_account: usize,
+    /// `None` leaves only the per-account bound.
+    pub global: Option<usize>,
+}
+
+impl Default for InFlightWindow {
+    fn default() -> Self {
+        Self {
+            per_account: 1,
+            global: None,
+        }
+    }
+}
+
+struct Account {
+    contract: Lock<SignerProvider>,
+    nonces: Arc<NonceAllocator>,
+}
+
+/// Sends `Lock::inc()` with the fees of the run, spread round-robin across
+/// the accounts of a [`WalletPool`], recovering from rejected submissions
+/// as the [`RecoveryPolicy`] says.
+pub struct IncWorkload {
+    accounts: Vec<Account>,
+    fees: Arc<FeeOracle>,
+    window: InFlightWindow,
+    policy: RecoveryPolicy,
+    abort: OnceLock<SubmitErrorKind>,
+}
+
+impl IncWorkload {
+    pub fn new(
+        contract: Address,
+        pool: &WalletPool,
+        fees: Arc<FeeOracle>,
+        window: InFlightWindow,
+        policy: RecoveryPolicy,
+    ) -> Self {
+        Self {
+            accounts: pool
+                .accounts()
+                .iter()
+                .map(|account| Account {
+                    contract: Lock::new(contract, account.clone()),
+                    nonces: Arc::new(NonceAllocator::new(account.clone())),
+                })
+                .collect(),
+            fees,
+            window,
+            policy,
+            abort: OnceLock::new(),
+        }
+    }
+
+    pub fn accounts(&self) -> usize {
+        self.accounts.len()
+    }
+
+    /// The nonce allocator of every account, for the nonce supervisor.
+    pub fn nonce_allocators(&self) -> Vec<Arc<NonceAllocator>> {
+        self.accounts.iter().map(|account| account.nonces.clone()).collect()
+    }
+
+    /// Account that sends transaction `idx`.
+    pub fn account_of(&self, idx: u64) -> usize {
+        (idx % self.accounts.len() as u64) as usize
+    }
+
+    async fn submit(&self, idx: u64, mut nonce: U256) -> SentTx {
+        let account = &self.accounts[self.account_of(idx)];
+        let signer = account.contract.client();
+        let from = signer.inner().address();
+        let sent_at = Instant::now();
+
+        let mut tx = account.contract.inc().tx;
+        tx.set_nonce(nonce);
+        if let Err(err) = self.fees.prepare(&signer, &mut tx).await {
+            tracing::warn!(target: COUNTER_CLIENT, "idx:{} from:{:?} tx prepare failed with err: {}", idx, from, err);
+            account.nonces.release(nonce);
+            return SentTx::failed(idx, from, Some(nonce), sent_at, err.to_string());
+        }
+
+        let mut errors = vec![];
+        let mut retry = 0;
+        loop {
+            // Bypass the nonce manager's send: its resync-on-error would
+            // reassign nonces under txs that are still in flight.
+            let err = match signer.inner().send_transaction(tx.clone(), None).await {
+                Ok(pending) => {
+                    tracing::info!(target: COUNTER_CLIENT, "idx:{} from:{:?} tx info: {:?}", idx, from, pending);
+                    account.nonces.sent(nonce, tx, pending.tx_hash());
+                    return SentTx::submitted(idx, from, pending.tx_hash(), nonce, sent_at)
+                        .with_submit_errors(errors);
+                }
+                Err(err) => SubmitError::classify(&err),
+            };
+            errors.push(err.kind);
+            // A retry of a tx that reached the node after all, e.g. after a
+            // timeout. It is followed under the hash it was sent with.
+            if err.kind == SubmitErrorKind::AlreadyKnown {
+                match signed_hash(signer.inner(), &tx).await {
+                    Ok(hash) => {
+                        tracing::info!(target: COUNTER_CLIENT, "idx:{} from:{:?} tx {:?} already known to the node", idx, from, hash);
+                        account.nonces.sent(nonce, tx, hash);
+                        return SentTx::submitted(idx, from, hash, nonce, sent_at)
+                            .with_submit_errors(errors);
+                    }
+                    Err(hash_err) => {
+                        tracing::warn!(target: COUNTER_CLIENT, "idx:{} from:{:?} hashing the known tx failed with err: {}", idx, from, hash_err);
+                    }
+                }
+            }
+            let mut recovery = self.policy.recovery(err.kind);
+            let retries = !matches!(recovery, Recovery::Abort | Recovery::Fail);
+            if retries && (retry >= self.policy.max_retries || self.aborted().is_some()) {
+                recovery = Recovery::Fail;
+            }
+            tracing::warn!(
+                target: COUNTER_CLIENT,
+                "idx:{} from:{:?} nonce:{} tx failed with err: {}, {}",
+                idx,
+                from,
+                nonce,
+                err,
+                recovery
+            );
+
+            match recovery {
+                Recovery::ResyncNonce => match account.nonces.resync(nonce).await {
+                    Ok(pending) => {
+                        nonce = account.nonces.next();
+                        tracing::info!(target: COUNTER_CLIENT, "from:{:?} pending nonce {}, retrying with {}", from, pending, nonce);
+                        tx.set_nonce(nonce);
+                    }
+                    Err(resync_err) => {
+                        tracing::warn!(target: COUNTER_CLIENT, "from:{:?} nonce resync failed with err: {}", from, resync_err);
+                        return self.reject(account, idx, from, nonce, sent_at, err, errors);
+                    }
+                },
+                Recovery::BumpFee => {
+                    let fees = Fees::of(&tx).unwrap_or_else(|| self.fees.current());
+                    fees.bumped(self.policy.fee_bump_percent).apply(&mut tx);
+                }
+                Recovery::BackOff => tokio::time::sleep(self.policy.backoff(retry)).await,
+                Recovery::Abort => {
+                    if self.abort.set(err.kind).is_ok() {
+                        tracing::error!(target: COUNTER_CLIENT, "aborting the run after {}", err);
+                    }
+                    return self.reject(account, idx, from, nonce, sent_at, err, errors);
+                }
+                Recovery::Fail => return self.reject(account, idx, from, nonce, sent_at, err, errors),
+            }
+            retry += 1;
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn reject(
+        &self,
+        account: &Account,
+        idx: u64,
+        from: Address,
+        nonce: U256,
+        sent_at: Instant,
+        err: SubmitError,
+        errors: Vec<SubmitErrorKind>,
+    ) -> SentTx {
+        if err.kind.frees_nonce() {
+            account.nonces.release(nonce);
+        } else {
+            account.nonces.forget(nonce);
+        }
+        SentTx::rejected(idx, from, nonce, sent_at, err).with_submit_errors(errors)
+    }
+
+    /// The error that stopped the run, if one did.
+    pub fn aborted(&self) -> Option<SubmitErrorKind> {
+        self.abort.get().copied()
+    }
+
+    /// Sends on every scheduler tick until the profile is exhausted. Each
+    /// account has its own lane that hands out nonces in order and pipelines
+    /// up to the in-flight window of submissions. Every tx goes to `sent` as
+    /// its submission returns; the channel closes after the last one.
+    pub async fn run(
+        self: Arc<Self>,
+        scheduler: &mut Scheduler,
+        sent: mpsc::UnboundedSender<SentTx>,
+    ) -> anyhow::Result<()> {
+        let global = self.window.global.map(|n| Arc::new(Semaphore::new(n.max(1))));
+        let lanes: Vec<_> = (0..self.accounts())
+            .map(|account| {
+                let (lane_tx, lane_rx) = mpsc::unbounded_channel::<u64>();
+                tokio::spawn(self.clone().lane(account, lane_rx, global.clone(), sent.clone()));
+                lane_tx
+            })
+            .collect();
+        drop(sent);
+
+        while let Some(idx) = scheduler.next().await {
+            if self.aborted().is_some() {
+                break;
+            }
+            lanes[self.account_of(idx)].send(idx)?;
+        }
+        Ok(())
+    }
+
+    async fn lane(
+        self: Arc<Self>,
+        account: usize,
+        mut lane_rx: mpsc::UnboundedReceiver<u64>,
+        global: Option<Arc<Semaphore>>,
+        result_tx: mpsc::UnboundedSender<SentTx>,
+    ) {
+        let nonces = &self.accounts[account].nonces;
+        let from = self.accounts[account].contract.client().inner().address();
+        if let Err(err) = nonces.init().await {
+            tracing::warn!(target: COUNTER_CLIENT, "from:{:?} nonce init failed with err: {}", from, err);
+            while let Some(idx) = lane_rx.recv().await {
+                let failed = SentTx::failed(idx, from, None, Instant::now(), err.to_string());
+                let _ = result_tx.send(failed);
+            }
+            return;
+        }
+
+        let per_account = Arc::new(Semaphore::new(self.window.per_account.max(1)));
+        while let Some(idx) = lane_rx.recv().await {
+            let Ok(account_permit) = per_account.clone().acquire_owned().await else {
+                return;
+            };
+            let global_permit = match &global {
+                Some(global) => match global.clone().acquire_owned().await {
+                    Ok(permit) => Some(permit),
+                    Err(_) => return,
+                },
+                None => None,
+            };
+            // The backlog of an aborted run is recorded, not sent.
+            if let Some(kind) = self.aborted() {
+                let skipped = format!("not sent, the run was aborted on {}", kind);
+                let _ = result_tx.send(SentTx::failed(idx, from, None, Instant::now(), skipped));
+                continue;
+            }
+            // Nonces are taken in lane order, only the submissions race.
+            let nonce = nonces.next();
+            let workload = self.clone();
+            let result_tx = result_tx.clone();
+            tokio::spawn(async move {
+                let sent = workload.submit(idx, nonce).await;
+                drop((account_permit, global_permit));
+                let _ = result_tx.send(sent);
+            });
+        }
+    }
+}
+
+/// Hash of `tx` as the signer middleware sends it.
+async fn signed_hash(
+    signer: &SignerWithoutNonceProvider,
+    tx: &TypedTransaction,
+) -> anyhow::Result<TxHash> {
+    let mut tx = tx.clone();
+    signer.fill_transaction(&mut tx, None).await?;
+    let signature = signer.signer().sign_transaction(&tx).await?;
+    Ok(tx.hash(&signature))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Mutex, time::Duration};
+
+    use ethers::{signers::LocalWallet, types::Bytes, utils::keccak256};
+    use serde_json::json;
+
+    use super::*;
+    use crate::{
+        chain::ChainCapabilities,
+        fees::{FeeConfig, FeeOracle, FeeStrategy, GasPolicy},
+        get_wallet_signer_provider, mock_rpc,
+        nonce::Slot,
+        tracker::TxStatus,
+    };
+
+    const CHAIN_ID: u64 = 1337;
+
+    /// A workload of one account whose `eth_sendRawTransaction` answers
+    /// `replies` in turn, with the raw txs it was sent.
+    async fn workload(replies: Vec<mock_rpc::Reply>) -> (IncWorkload, Arc<Mutex<Vec<Bytes>>>) {
+        let sent = Arc::new(Mutex::new(vec![]));
+        let replies = Mutex::new(replies.into_iter());
+        let raw_txs = sent.clone();
+        let provider = mock_rpc::provider(move |method, params| match method {
+            "eth_getTransactionCount" => Ok(json!("0x5")),
+            "eth_sendRawTransaction" => {
+                raw_txs
+                    .lock()
+                    .unwrap()
+                    .push(serde_json::from_value(params[0].clone()).unwrap());
+                replies.lock().unwrap().next().expect("no reply left")
+            }
+            _ => Err(format!("unexpected {}", method)),
+        })
+        .await;
+        let wallet: LocalWallet = "11".repeat(32).parse().unwrap();
+        let account = Arc::new(get_wallet_signer_provider(
+            provider.clone(),
+            CHAIN_ID,
+            wallet,
+        ));
+        let fees = FeeOracle::new(
+            provider,
+            ChainCapabilities {
+                chain_id: CHAIN_ID,
+                london: false,
+                max_priority_fee: false,
+            },
+            FeeConfig {
+                strategy: FeeStrategy::Legacy {
+                    gas_price: Some(1_000_000_000.into()),
+                },
+                base_fee_multiplier: 1.0,
+                refresh_interval: Duration::from_secs(60),
+                gas: GasPolicy::Fixed(100_000),
+            },
+        )
+        .await
+        .unwrap();
+        let policy = RecoveryPolicy {
+            backoff: Duration::from_millis(1),
+            ..Default::default()
+        };
+        let workload = IncWorkload::new(
+            Address::repeat_byte(0x22),
+            &WalletPool::single(account),
+            fees,
+            InFlightWindow::default(),
+            policy,
+        );
+        (workload, sent)
+    }
+
+    #[tokio::test]
+    async fn timed_out_tx_the_node_already_knows_is_submitted() {
+        let (workload, sent) = workload(vec![
+            Err("request timed out".to_string()),
+            Err("already known".to_string()),
+        ])
+        .await;
+        let nonces = &workload.accounts[0].nonces;
+        nonces.init().await.unwrap();
+        let nonce = nonces.next();
+
+        let tx = workload.submit(0, nonce).await;
+
+        let sent = sent.lock().unwrap().clone();
+        assert_eq!(sent.len(), 2);
+        assert_eq!(sent[0], sent[1], "the retry must resend the identical tx");
+        let hash = TxHash::from(keccak256(&sent[0]));
+        assert_eq!(tx.status, TxStatus::Pending);
+        assert_eq!(tx.hash, Some(hash));
+        assert_eq!(
+            tx.submit_errors,
+            [SubmitErrorKind::Timeout, SubmitErrorKind::AlreadyKnown]
+        );
+        assert!(matches!(nonces.slot(nonce), Some(Slot::Sent { hash: sent, .. }) if sent == hash));
+        // The nonce stays taken, the next tx gets a fresh one.
+        assert_eq!(nonces.next(), nonce + 1);
+    }
+
+    #[tokio::test]
+    async fn fees_too_low_for_the_pool_free_the_nonce() {
+        let (workload, sent) = workload(vec![Err("transaction underpriced".to_string()); 4]).await;
+        let nonces = &workload.accounts[0].nonces;
+        nonces.init().await.unwrap();
+        let nonce = nonces.next();
+
+        let tx = workload.submit(0, nonce).await;
+
+        // Bumped on every retry until they ran out.
+        let sent = sent.lock().unwrap().clone();
+        assert_eq!(sent.len(), 4);
+        assert!(sent.windows(2).all(|pair| pair[0] != pair[1]));
+        assert_eq!(tx.status, TxStatus::Failed);
+        assert_eq!(tx.error_kind, Some(SubmitErrorKind::FeeTooLow));
+        // Nothing holds the nonce, the next tx takes it.
+        assert!(nonces.slot(nonce).is_none());
+        assert_eq!(nonces.next(), nonce);
+    }
+}